@@ -0,0 +1,539 @@
+//! Derive macros for [easy-repl](https://docs.rs/easy-repl).
+//!
+//! This crate should not be used directly, the macros are re-exported by `easy-repl`
+//! when the `derive` feature is enabled.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Delimiter, TokenStream as TokenStream2, TokenTree};
+use quote::{quote, ToTokens};
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Expr, Fields, GenericArgument, LitChar,
+    PathArguments, Type,
+};
+
+/// Derive `ReplArgs` for a struct with named fields.
+///
+/// Each field corresponds to a single command argument, in the order of declaration.
+/// Field doc comments are used as argument descriptions. Fields are parsed depending
+/// on their type and attributes:
+///
+/// * `T` - required positional argument
+/// * `T` with `#[repl(default = expr)]` - positional argument that can be omitted
+/// * `Option<T>` - positional argument that can be omitted
+/// * `Vec<T>` - variadic argument, optionally with `#[repl(count = min..max)]`, must be the
+///   last positional argument
+/// * `bool` with `#[repl(flag)]` - flag, e.g. `--verbose`
+/// * `Option<T>` with `#[repl(option)]` - named option, e.g. `--timeout 30`
+/// * `T` with `#[repl(option, default = expr)]` - named option with default value
+///
+/// Positional arguments that can be omitted must come after all the required ones.
+/// Flags and named options can have a short name specified with `#[repl(short = 'x')]`.
+/// Values of arguments are completed using `#[repl(complete = expr)]`, where `expr`
+/// implements `ArgCompleter`, or else using the completer of the value type, if any.
+#[proc_macro_derive(ReplArgs, attributes(repl))]
+pub fn derive_repl_args(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `Choice` for an enum with unit variants.
+///
+/// Also implements `FromStr`, `Display` and `Completable`, so that the enum can be used
+/// as an argument type. Variants are named using kebab-case, e.g. `DryRun` is `dry-run`,
+/// which can be changed with `#[repl(name = "...")]`.
+#[proc_macro_derive(Choice, attributes(repl))]
+pub fn derive_choice(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_choice(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Convert `CamelCase` identifier to `kebab-case`
+fn kebab_case(ident: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in ident.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            out.push('-');
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+fn variant_name(variant: &syn::Variant) -> syn::Result<String> {
+    let mut name = None;
+    for attr in variant.attrs.iter().filter(|a| a.path().is_ident("repl")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                let value: syn::LitStr = meta.value()?.parse()?;
+                name = Some(value.value());
+                Ok(())
+            } else {
+                Err(meta.error("unsupported repl attribute"))
+            }
+        })?;
+    }
+    Ok(name.unwrap_or_else(|| kebab_case(&variant.ident.to_string())))
+}
+
+fn expand_choice(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new(
+            input.ident.span(),
+            "Choice can only be derived for enums",
+        ));
+    };
+    if let Some(variant) = data.variants.iter().find(|v| !v.fields.is_empty()) {
+        return Err(syn::Error::new(
+            variant.span(),
+            "Choice can only be derived for enums with unit variants",
+        ));
+    }
+    let names = data
+        .variants
+        .iter()
+        .map(variant_name)
+        .collect::<syn::Result<Vec<_>>>()?;
+    let variants: Vec<_> = data.variants.iter().map(|v| &v.ident).collect();
+    let indices = 0..variants.len();
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::easy_repl::Choice for #ident #ty_generics #where_clause {
+            fn choices() -> &'static [&'static str] {
+                &[ #(#names),* ]
+            }
+
+            fn from_choice(index: usize) -> Self {
+                match index {
+                    #( #indices => Self::#variants, )*
+                    _ => ::core::panic!("invalid choice index: {}", index),
+                }
+            }
+        }
+
+        impl #impl_generics ::core::str::FromStr for #ident #ty_generics #where_clause {
+            type Err = ::easy_repl::command::ChoiceError;
+
+            fn from_str(value: &str) -> ::core::result::Result<Self, Self::Err> {
+                <Self as ::easy_repl::Choice>::parse_choice(value)
+            }
+        }
+
+        impl #impl_generics ::core::fmt::Display for #ident #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                match self {
+                    #( Self::#variants => f.write_str(#names), )*
+                }
+            }
+        }
+
+        impl #impl_generics ::easy_repl::completion::Completable for #ident #ty_generics #where_clause {
+            fn completer() -> ::std::sync::Arc<dyn ::easy_repl::completion::ArgCompleter> {
+                ::std::sync::Arc::new(<Self as ::easy_repl::Choice>::choices())
+            }
+        }
+    })
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    flag: bool,
+    option: bool,
+    short: Option<String>,
+    default: Option<Expr>,
+    count: Option<Expr>,
+    complete: Option<Expr>,
+}
+
+impl FieldAttrs {
+    fn parse(field: &syn::Field) -> syn::Result<Self> {
+        let mut attrs = FieldAttrs::default();
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("repl")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("flag") {
+                    attrs.flag = true;
+                } else if meta.path.is_ident("option") {
+                    attrs.option = true;
+                } else if meta.path.is_ident("short") {
+                    let short: LitChar = meta.value()?.parse()?;
+                    attrs.short = Some(short.value().to_string());
+                } else if meta.path.is_ident("default") {
+                    attrs.default = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("count") {
+                    attrs.count = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("complete") {
+                    attrs.complete = Some(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("unsupported repl attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(attrs)
+    }
+}
+
+/// Join lines of doc comments into a single description
+fn doc_comment(field: &syn::Field) -> Option<String> {
+    let lines: Vec<_> = field
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("doc"))
+        .filter_map(|a| match &a.meta {
+            syn::Meta::NameValue(nv) => match &nv.value {
+                Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(s),
+                    ..
+                }) => Some(s.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    let doc = lines.join(" ").trim().to_string();
+    (!doc.is_empty()).then_some(doc)
+}
+
+/// If the type is `wrapper<T>` return `T`
+fn inner_type<'t>(ty: &'t Type, wrapper: &str) -> Option<&'t Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first() {
+        Some(GenericArgument::Type(inner)) if args.args.len() == 1 => Some(inner),
+        _ => None,
+    }
+}
+
+/// Convert tokens to a string formatted like `stringify!` does for source code tokens
+fn tokens_to_string(tokens: TokenStream2) -> String {
+    let mut out = String::new();
+    let mut prev_word = false;
+    for token in tokens {
+        match token {
+            TokenTree::Ident(_) | TokenTree::Literal(_) => {
+                if prev_word {
+                    out.push(' ');
+                }
+                out.push_str(&token.to_string());
+                prev_word = true;
+            }
+            TokenTree::Punct(punct) => {
+                out.push(punct.as_char());
+                if matches!(punct.as_char(), ',' | ';') {
+                    out.push(' ');
+                }
+                prev_word = false;
+            }
+            TokenTree::Group(group) => {
+                let inner = tokens_to_string(group.stream());
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::Brace => ("{ ", " }"),
+                    Delimiter::None => ("", ""),
+                };
+                out.push_str(open);
+                out.push_str(&inner);
+                out.push_str(close);
+                prev_word = false;
+            }
+        }
+    }
+    out
+}
+
+/// Kind of a positional argument, in the order in which they must be given
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Position {
+    Required,
+    Optional,
+    Variadic,
+}
+
+struct Arg {
+    kind: TokenStream2,
+    /// [`None`] for flags/named options
+    position: Option<Position>,
+    /// Expression evaluating to argument info in the same format as generated by `command!`
+    info: TokenStream2,
+    value: TokenStream2,
+    completer: TokenStream2,
+}
+
+fn field_arg(field: &syn::Field, num: usize) -> syn::Result<Arg> {
+    let attrs = FieldAttrs::parse(field)?;
+    let ty = &field.ty;
+    let name = field.ident.as_ref().unwrap().to_string();
+    let to_string = |t: &dyn ToTokens| tokens_to_string(t.to_token_stream());
+    // info with `{}` replaced by the type name, or the list of choices for `Choice` types
+    let info = |fmt: String, ty: &Type| {
+        let ty_name = to_string(ty);
+        quote! {
+            ::std::format!(#fmt, {
+                #[allow(unused_imports)]
+                use ::easy_repl::command::{ChoiceTypeInfo as _, OtherTypeInfo as _};
+                (&::easy_repl::command::TypeInfo::<#ty>::new()).type_info(#ty_name)
+            })
+        }
+    };
+    let default_string = |default: &Expr| to_string(default).replace('{', "{{").replace('}', "}}");
+
+    let short = match &attrs.short {
+        Some(short) => quote!(::core::option::Option::Some(#short)),
+        None => quote!(::core::option::Option::None),
+    };
+    let option_name = match &attrs.short {
+        Some(short) => format!("-{short}, --{name}"),
+        None => format!("--{name}"),
+    };
+    let values = quote!(values[#num]);
+    let parse_first = |ty: &Type| {
+        quote! {
+            #values.first()
+                .map(|value| ::easy_repl::command::parse_arg::<#ty>(value))
+                .transpose()?
+        }
+    };
+    let parse_default = |default: &Expr| {
+        quote! {
+            match #values.first() {
+                ::core::option::Option::Some(value) => ::easy_repl::command::parse_arg::<#ty>(value)?,
+                ::core::option::Option::None => #default,
+            }
+        }
+    };
+
+    if attrs.short.is_some() && !(attrs.flag || attrs.option) {
+        return Err(syn::Error::new(
+            field.span(),
+            "`short` can only be used with `flag` or `option`",
+        ));
+    }
+    if attrs.count.is_some() && (attrs.flag || attrs.option || inner_type(ty, "Vec").is_none()) {
+        return Err(syn::Error::new(
+            field.span(),
+            "`count` can only be used with positional `Vec<T>`",
+        ));
+    }
+
+    // type of values, used to select the completer
+    let value_type = inner_type(ty, "Option")
+        .or_else(|| inner_type(ty, "Vec"))
+        .unwrap_or(ty);
+    let completer = match &attrs.complete {
+        Some(complete) => quote! {
+            ::core::option::Option::Some(
+                ::std::sync::Arc::new(#complete) as ::std::sync::Arc<dyn ::easy_repl::completion::ArgCompleter>
+            )
+        },
+        None => quote! {{
+            #[allow(unused_imports)]
+            use ::easy_repl::completion::{CompletableType as _, OtherType as _};
+            (&::easy_repl::completion::TypeCompleter::<#value_type>::new()).completer()
+        }},
+    };
+
+    if attrs.flag && attrs.complete.is_some() {
+        return Err(syn::Error::new(
+            field.span(),
+            "`complete` cannot be used with `flag`",
+        ));
+    }
+
+    let arg = if attrs.flag {
+        if attrs.option || attrs.default.is_some() {
+            return Err(syn::Error::new(
+                field.span(),
+                "`flag` cannot be combined with `option` or `default`",
+            ));
+        }
+        Arg {
+            kind: quote!(::easy_repl::command::ArgKind::Flag { long: #name, short: #short }),
+            position: None,
+            info: quote!(::std::string::String::from(#option_name)),
+            value: quote!(!#values.is_empty()),
+            completer: quote!(::core::option::Option::None),
+        }
+    } else if attrs.option {
+        let kind = quote!(::easy_repl::command::ArgKind::Named { long: #name, short: #short });
+        match (&attrs.default, inner_type(ty, "Option")) {
+            (Some(default), _) => Arg {
+                kind,
+                position: None,
+                info: info(
+                    format!("{option_name} <{{}}={}>", default_string(default)),
+                    ty,
+                ),
+                value: parse_default(default),
+                completer,
+            },
+            (None, Some(inner)) => Arg {
+                kind,
+                position: None,
+                info: info(format!("{option_name} <{{}}>"), inner),
+                value: parse_first(inner),
+                completer,
+            },
+            (None, None) => {
+                return Err(syn::Error::new(
+                    ty.span(),
+                    "`option` must be of type `Option<T>` or have a `default` value",
+                ))
+            }
+        }
+    } else if let Some(inner) = inner_type(ty, "Vec") {
+        let count = attrs
+            .count
+            .as_ref()
+            .map_or_else(|| quote!(..), |c| quote!(#c));
+        Arg {
+            kind: quote!(::easy_repl::command::ArgKind::variadic(#count)),
+            position: Some(Position::Variadic),
+            info: info(format!("{name}:{{}}..."), inner),
+            value: quote!(::easy_repl::command::parse_variadic::<#inner>(&#values)?),
+            completer,
+        }
+    } else if let Some(default) = &attrs.default {
+        Arg {
+            kind: quote!(::easy_repl::command::ArgKind::Optional),
+            position: Some(Position::Optional),
+            info: info(format!("[{name}:{{}}={}]", default_string(default)), ty),
+            value: parse_default(default),
+            completer,
+        }
+    } else if let Some(inner) = inner_type(ty, "Option") {
+        Arg {
+            kind: quote!(::easy_repl::command::ArgKind::Optional),
+            position: Some(Position::Optional),
+            info: info(format!("[{name}:{{}}]"), inner),
+            value: parse_first(inner),
+            completer,
+        }
+    } else {
+        Arg {
+            kind: quote!(::easy_repl::command::ArgKind::Required),
+            position: Some(Position::Required),
+            info: info(format!("{name}:{{}}"), ty),
+            value: quote!(::easy_repl::command::parse_arg::<#ty>(#values[0])?),
+            completer,
+        }
+    };
+    Ok(arg)
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            Fields::Unit => return expand_fields(&input, &Default::default()),
+            Fields::Unnamed(_) => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "ReplArgs can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "ReplArgs can only be derived for structs",
+            ))
+        }
+    };
+    expand_fields(&input, fields)
+}
+
+fn expand_fields(
+    input: &DeriveInput,
+    fields: &syn::punctuated::Punctuated<syn::Field, syn::Token![,]>,
+) -> syn::Result<TokenStream2> {
+    let args = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| field_arg(field, i))
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let mut last = Position::Required;
+    for (field, arg) in fields.iter().zip(args.iter()) {
+        match arg.position {
+            Some(_) if last == Position::Variadic => {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "`Vec<T>` must be the last positional argument",
+                ))
+            }
+            Some(position) if position < last => {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "required arguments must come before optional ones",
+                ))
+            }
+            Some(position) => last = position,
+            None => {}
+        }
+    }
+
+    let kinds = args.iter().map(|a| &a.kind);
+    let completers = args.iter().map(|a| {
+        let (kind, completer) = (&a.kind, &a.completer);
+        quote!((#kind, #completer))
+    });
+    let args_info = args
+        .iter()
+        .filter(|a| a.position.is_some())
+        .map(|a| &a.info);
+    let options_info = args
+        .iter()
+        .filter(|a| a.position.is_none())
+        .map(|a| &a.info);
+    let descriptions = fields.iter().zip(args.iter()).filter_map(|(field, arg)| {
+        let info = &arg.info;
+        doc_comment(field).map(|doc| quote!((#info, #doc.into())))
+    });
+    let names = fields.iter().map(|f| f.ident.as_ref().unwrap());
+    let values = args.iter().map(|a| &a.value);
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::easy_repl::ReplArgs for #ident #ty_generics #where_clause {
+            fn arg_kinds() -> ::std::vec::Vec<::easy_repl::command::ArgKind> {
+                ::std::vec![ #(#kinds),* ]
+            }
+
+            fn args_info() -> ::std::vec::Vec<::std::string::String> {
+                ::std::vec![ #(#args_info),* ]
+            }
+
+            fn options_info() -> ::std::vec::Vec<::std::string::String> {
+                ::std::vec![ #(#options_info),* ]
+            }
+
+            fn arg_descriptions() -> ::std::vec::Vec<(::std::string::String, ::std::string::String)> {
+                ::std::vec![ #(#descriptions),* ]
+            }
+
+            fn arg_completers() -> ::easy_repl::completion::ArgCompleters {
+                ::std::vec![ #(#completers),* ]
+            }
+
+            #[allow(unused_variables)]
+            fn from_values(
+                values: &[::std::vec::Vec<&str>],
+            ) -> ::core::result::Result<Self, ::easy_repl::command::ArgsError> {
+                ::core::result::Result::Ok(Self { #(#names: #values),* })
+            }
+        }
+    })
+}
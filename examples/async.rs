@@ -0,0 +1,49 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{self, Context};
+use easy_repl::{async_command, command, CommandStatus, Repl};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    // background task keeps running while the REPL waits for input
+    let ticks = Arc::new(AtomicU64::new(0));
+    tokio::spawn({
+        let ticks = ticks.clone();
+        async move {
+            loop {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                ticks.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+
+    #[rustfmt::skip]
+    let mut repl = Repl::builder()
+        .add("sleep", async_command! {
+            "Sleep for given number of milliseconds",
+            (ms: u64) => |ms| async move {
+                tokio::time::sleep(Duration::from_millis(ms)).await;
+                println!("Slept for {} ms", ms);
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("ticks", command! {
+            "Show the number of ticks of the background task",
+            () => || {
+                println!("{}", ticks.load(Ordering::Relaxed));
+                Ok(CommandStatus::Done)
+            }
+        })
+        .build_async()
+        .context("Failed to create repl")?;
+
+    repl.run().await.context("Critical REPL error")?;
+
+    Ok(())
+}
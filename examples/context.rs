@@ -0,0 +1,40 @@
+use anyhow::{self, Context};
+use easy_repl::{command, CommandStatus, Repl, ReplContext};
+
+fn main() -> anyhow::Result<()> {
+    #[rustfmt::skip]
+    let mut repl = Repl::builder()
+        .description("Example showing how commands can modify the REPL")
+        .add("use", command! {
+            "Start using a device",
+            (ctx: &mut ReplContext, device: String) => |ctx: &mut ReplContext, device: String| {
+                ctx.set_prompt(format!("{}> ", device));
+                ctx.add_command("reset", command! {
+                    "Reset the device",
+                    () => || {
+                        println!("Resetting the device");
+                        Ok(CommandStatus::Done)
+                    }
+                });
+                ctx.add_command("leave", command! {
+                    "Stop using the device",
+                    (ctx: &mut ReplContext) => |ctx: &mut ReplContext| {
+                        ctx.set_prompt("> ");
+                        ctx.remove_command("reset");
+                        ctx.remove_command("leave");
+                        ctx.set_enabled("use", true);
+                        Ok(CommandStatus::Done)
+                    }
+                });
+                ctx.set_enabled("use", false);
+                println!("Using {}, type 'help' to see the device commands", device);
+                Ok(CommandStatus::Done)
+            }
+        })
+        .build()
+        .context("Failed to create repl")?;
+
+    repl.run().context("Critical REPL error")?;
+
+    Ok(())
+}
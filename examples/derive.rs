@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use anyhow::{self, Context};
+use easy_repl::{Choice, Command, CommandStatus, Repl, ReplArgs};
+
+#[derive(ReplArgs)]
+struct CopyArgs {
+    /// Destination directory
+    destination: PathBuf,
+    /// Files to be copied
+    #[repl(count = 1..)]
+    sources: Vec<PathBuf>,
+    /// Overwrite existing files
+    #[repl(flag, short = 'f')]
+    force: bool,
+    /// Stop after copying this number of files
+    #[repl(option, short = 'n')]
+    limit: Option<usize>,
+}
+
+#[derive(Choice)]
+enum Style {
+    Plain,
+    Loud,
+}
+
+#[derive(ReplArgs)]
+struct GreetArgs {
+    /// Who to greet
+    #[repl(default = String::from("world"))]
+    name: String,
+    /// How many times to repeat the greeting
+    #[repl(option, short = 'r', default = 1)]
+    repeat: usize,
+    /// Greeting style
+    #[repl(option, default = Style::Plain)]
+    style: Style,
+}
+
+fn main() -> anyhow::Result<()> {
+    #[rustfmt::skip]
+    let mut repl = Repl::builder()
+        .add("copy", Command::from_args("Pretend to copy files", |args: CopyArgs| {
+            let limit = args.limit.unwrap_or(args.sources.len());
+            for source in args.sources.iter().take(limit) {
+                let action = if args.force { "Overwriting" } else { "Copying" };
+                println!("{} {:?} -> {:?}", action, source, args.destination);
+            }
+            Ok(CommandStatus::Done)
+        }))
+        .add("greet", Command::from_args("Greet someone", |args: GreetArgs| {
+            let greeting = match args.style {
+                Style::Plain => format!("Hello {}!", args.name),
+                Style::Loud => format!("HELLO {}!!!", args.name.to_uppercase()),
+            };
+            for _ in 0..args.repeat {
+                println!("{}", greeting);
+            }
+            Ok(CommandStatus::Done)
+        }))
+        .build()
+        .context("Failed to create repl")?;
+
+    repl.run().context("Critical REPL error")?;
+
+    Ok(())
+}
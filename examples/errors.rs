@@ -6,7 +6,7 @@ use easy_repl::{command, CommandStatus, Critical, Repl};
 // this could be any funcion returining Result with an error implementing Error
 // here for simplicity we make use of the Other variant of std::io::Error
 fn may_throw(description: String) -> Result<(), std::io::Error> {
-    Err(std::io::Error::new(std::io::ErrorKind::Other, description))
+    Err(std::io::Error::other(description))
 }
 
 fn main() -> anyhow::Result<()> {
@@ -0,0 +1,46 @@
+use anyhow::{self, Context};
+use easy_repl::{command, CommandStatus, Repl};
+
+fn main() -> anyhow::Result<()> {
+    #[rustfmt::skip]
+    let mut repl = Repl::builder()
+        .description("Example with groups of commands, try `help net`")
+        .add("status", command! {
+            "Show status",
+            () => || {
+                println!("All good");
+                Ok(CommandStatus::Done)
+            }
+        })
+        .group("net", |g| g
+            .description("Network configuration")
+            .add("show", command! {
+                "Show network configuration",
+                () => || {
+                    println!("eth0: 192.168.0.2");
+                    Ok(CommandStatus::Done)
+                }
+            })
+            .group("route", |g| g
+                .description("Routing table")
+                .add("add", command! {
+                    "Add a route",
+                    (addr: String, via: String) => |addr, via| {
+                        println!("Adding route {} via {}", addr, via);
+                        Ok(CommandStatus::Done)
+                    }
+                })
+                .add("del", command! {
+                    "Delete a route",
+                    (addr: String) => |addr| {
+                        println!("Deleting route {}", addr);
+                        Ok(CommandStatus::Done)
+                    }
+                })))
+        .build()
+        .context("Failed to create repl")?;
+
+    repl.run().context("Critical REPL error")?;
+
+    Ok(())
+}
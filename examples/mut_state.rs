@@ -9,7 +9,7 @@ fn main() -> anyhow::Result<()> {
     let mut repl = Repl::builder()
         .description("Example REPL")
         .prompt("=> ")
-        .text_width(60 as usize)
+        .text_width(60_usize)
         .add("count", command! {
             "Count from X to Y",
             (X:i32, Y:i32) => |x, y| {
@@ -37,16 +37,16 @@ fn main() -> anyhow::Result<()> {
         })
         // this shows how to create Command manually with the help of the validator! macro
         // one could also implement arguments validation manually
-        .add("outy", easy_repl::Command {
-            description: "Use mutably outside var y".into(),
-            args_info: vec!["appended".into()],
-            handler: Box::new(|args| {
+        .add("outy", {
+            let mut cmd = easy_repl::Command::new("Use mutably outside var y", |args| {
                 let validator = validator!(i32);
                 validator(args)?;
                 outside_y += args[0];
                 println!("{}", outside_y);
                 Ok(CommandStatus::Done)
-            }),
+            });
+            cmd.args_info = vec!["appended".into()];
+            cmd
         })
         .build().context("Failed to create repl")?;
 
@@ -0,0 +1,52 @@
+use anyhow::{self, Context};
+use easy_repl::{command, CommandStatus, Repl};
+
+/// Application state shared by all the commands, no need for `RefCell`
+#[derive(Default)]
+struct Counter {
+    value: i32,
+    history: Vec<i32>,
+}
+
+fn main() -> anyhow::Result<()> {
+    #[rustfmt::skip]
+    let mut repl = Repl::builder()
+        .with_state(Counter::default())
+        .add("inc", command! {
+            "Increment counter",
+            (counter: &mut Counter, by: i32 = 1) => |counter: &mut Counter, by| {
+                counter.history.push(counter.value);
+                counter.value += by;
+                println!("counter = {}", counter.value);
+                Ok(CommandStatus::Done)
+            },
+        })
+        .add("dec", command! {
+            "Decrement counter",
+            (counter: &mut Counter, by: i32 = 1) => |counter: &mut Counter, by| {
+                counter.history.push(counter.value);
+                counter.value -= by;
+                println!("counter = {}", counter.value);
+                Ok(CommandStatus::Done)
+            },
+        })
+        .add("undo", command! {
+            "Undo last change",
+            (counter: &mut Counter) => |counter: &mut Counter| {
+                if let Some(value) = counter.history.pop() {
+                    counter.value = value;
+                }
+                println!("counter = {}", counter.value);
+                Ok(CommandStatus::Done)
+            },
+        })
+        .build()
+        .context("Failed to create repl")?;
+
+    repl.run().context("Critical REPL error")?;
+
+    let counter = repl.state::<Counter>().unwrap();
+    println!("Final value: {}", counter.value);
+
+    Ok(())
+}
@@ -0,0 +1,326 @@
+//! REPL with async command handlers.
+//!
+//! Commands created with [`async_command!`](crate::async_command!) return futures, which are
+//! awaited by [`AsyncRepl`] after the handler returns. Lines are read with [`rustyline`] on
+//! a blocking thread (see [`tokio::task::spawn_blocking`]), so the runtime can make progress
+//! on other tasks while waiting for user input:
+//! ```rust
+//! # use std::time::Duration;
+//! # use easy_repl::{Repl, CommandStatus, async_command};
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let mut repl = Repl::builder()
+//!     .add("sleep", async_command! {
+//!         "Sleep for given number of milliseconds",
+//!         (ms: u64) => |ms| async move {
+//!             tokio::time::sleep(Duration::from_millis(ms)).await;
+//!             Ok(CommandStatus::Done)
+//!         }
+//!     })
+//! #   .line_source("sleep 1\n".as_bytes())
+//!     .build_async()
+//!     .expect("Failed to build REPL");
+//! repl.run().await.expect("Critical REPL error");
+//! # }
+//! ```
+//!
+//! Synchronous commands can be mixed with async ones. A [`Repl`] can still be built from the
+//! same builder, but async commands will fail when executed by it, e.g. from
+//! [`Repl::run`] or in the middle of a line with multiple commands, where the result of the
+//! command would be needed immediately.
+//!
+//! The futures are not required to be [`Send`], so [`AsyncRepl::run`] has to be awaited
+//! directly in the task that owns the REPL (e.g. in `main`), not spawned with [`tokio::spawn`].
+//!
+//! Custom [`LineSource`]s (see [`ReplBuilder::line_source`](crate::repl::ReplBuilder::line_source))
+//! cannot be moved to another thread, so they are read synchronously on the runtime thread,
+//! blocking other tasks until the line is returned.
+
+use std::{future::Future, pin::Pin};
+
+use rustyline::error::ReadlineError;
+use tokio::task::JoinHandle;
+
+use crate::command::CommandStatus;
+use crate::repl::{LoopStatus, Repl};
+use crate::source::{LineSource, ReplSource, RustylineSource};
+
+/// Future returned by async command handlers.
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<CommandStatus>> + 'a>>;
+
+/// [`Repl`] that awaits the futures returned by async commands, see the [module level documentation](self).
+///
+/// It is created with [`ReplBuilder::build_async`](crate::repl::ReplBuilder::build_async).
+pub struct AsyncRepl<'a> {
+    repl: Repl<'a>,
+    /// Line being read on a blocking thread, kept if the future reading it has been dropped
+    reading: Option<JoinHandle<(Box<RustylineSource>, rustyline::Result<String>)>>,
+}
+
+impl<'a> AsyncRepl<'a> {
+    /// Wrap a REPL so that it can execute async commands.
+    pub fn new(repl: Repl<'a>) -> Self {
+        AsyncRepl {
+            repl,
+            reading: None,
+        }
+    }
+
+    /// Access the underlying REPL.
+    pub fn repl(&mut self) -> &mut Repl<'a> {
+        &mut self.repl
+    }
+
+    /// Return the underlying REPL.
+    ///
+    /// If reading of a line has been cancelled and not resumed with [`AsyncRepl::next`],
+    /// the editor is still used by the blocking thread and the REPL is returned without it.
+    pub fn into_inner(self) -> Repl<'a> {
+        self.repl
+    }
+
+    /// Run a single REPL iteration and return whether this is the last one or not.
+    ///
+    /// Waiting for the line is cancel-safe: if the returned future is dropped before the line
+    /// has been read (e.g. in `tokio::select!`), the next call returns that line. Until then
+    /// the editor, with its history, is not available through [`AsyncRepl::repl`].
+    pub async fn next(&mut self) -> anyhow::Result<LoopStatus> {
+        self.repl.report_jobs()?;
+        match self.readline().await {
+            Ok(line) => {
+                self.repl.interrupted = false;
+                if !line.trim().is_empty() {
+                    self.repl.source.add_history_entry(line.trim());
+                    // before executing, so that the entry is kept if the command never returns
+                    self.repl.save_history()?;
+                    Ok(self
+                        .execute_line(&line)
+                        .await?
+                        .unwrap_or(LoopStatus::Continue))
+                } else {
+                    Ok(LoopStatus::Continue)
+                }
+            }
+            Err(err) => self.repl.readline_error(err),
+        }
+    }
+
+    /// Run the evaluation loop until [`LoopStatus::Break`] is received.
+    pub async fn run(&mut self) -> anyhow::Result<()> {
+        let result = loop {
+            match self.next().await {
+                Ok(LoopStatus::Continue) => {}
+                Ok(LoopStatus::Break) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
+        // history could also have been modified by commands
+        self.repl.save_history()?;
+        result
+    }
+
+    /// Read the next line, the default line source is used on a blocking thread,
+    /// custom ones synchronously.
+    async fn readline(&mut self) -> rustyline::Result<String> {
+        if self.reading.is_none() {
+            let prompt = self.repl.prompt.clone();
+            if let ReplSource::Custom(source) = &mut self.repl.source {
+                return source.readline(&prompt);
+            }
+            // the editor is moved to the blocking thread and put back when the line is read
+            let placeholder = ReplSource::Custom(Box::new(std::io::empty()));
+            let mut source = match std::mem::replace(&mut self.repl.source, placeholder) {
+                ReplSource::Rustyline(source) => source,
+                ReplSource::Custom(_) => unreachable!(),
+            };
+            self.repl.jobs.set_reading(true);
+            self.reading = Some(tokio::task::spawn_blocking(move || {
+                let line = source.readline(&prompt);
+                (source, line)
+            }));
+        }
+        // awaiting the handle by reference keeps it if this future is dropped
+        let result = self.reading.as_mut().unwrap().await;
+        self.reading = None;
+        self.repl.jobs.set_reading(false);
+        match result {
+            Ok((source, line)) => {
+                self.repl.source = ReplSource::Rustyline(source);
+                // commands could have changed while the editor was away
+                self.repl.update_completer();
+                line
+            }
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(err) => Err(ReadlineError::Io(std::io::Error::other(err))),
+        }
+    }
+
+    /// Execute a single line, awaiting async commands, see [`Repl::execute_line`].
+    pub(crate) async fn execute_line(
+        &mut self,
+        line: &str,
+    ) -> anyhow::Result<Result<LoopStatus, String>> {
+        let mut chain = match self.repl.chain(line)? {
+            Ok(chain) => chain,
+            Err(err) => return Ok(Err(err)),
+        };
+        while let Some(command) = chain.next_command() {
+            let result = self.execute_command(command).await?;
+            chain.set_result(result);
+        }
+        Ok(chain.result())
+    }
+
+    async fn execute_command(&mut self, line: &str) -> anyhow::Result<Result<LoopStatus, String>> {
+        let result = self.repl.execute_command(line)?;
+        match self.repl.pending.take() {
+            Some((name, future)) => {
+                let result = {
+                    let _guard = self.repl.catch_interrupt();
+                    future.await
+                };
+                self.repl.command_result(&name, result)
+            }
+            None => Ok(result),
+        }
+    }
+}
+
+/// Generate a [`Command`](crate::Command) with an async handler.
+///
+/// The syntax is the same as for [`command!`](crate::command!), but the handler has to return
+/// a future resolving to `anyhow::Result<CommandStatus>`, e.g. an `async move` block. Arguments
+/// are parsed before the handler is called, and the future is awaited by [`AsyncRepl`].
+/// The future cannot borrow from [`ReplContext`](crate::ReplContext) or the state, these have
+/// to be used in the handler before the future is created:
+/// ```rust
+/// # use easy_repl::{CommandStatus, ReplContext, async_command};
+/// let cmd = async_command! {
+///     "Fetch a resource",
+///     (ctx: &mut ReplContext, url: String, --retries: u32 = 3) => |ctx: &mut ReplContext, url: String, retries| {
+///         ctx.set_prompt(format!("{}> ", url));
+///         async move {
+///             for _ in 0..retries {
+///                 // ...
+///             }
+///             Ok(CommandStatus::Done)
+///         }
+///     }
+/// };
+/// assert_eq!(cmd.args_info, &["url:String"]);
+/// ```
+#[macro_export]
+macro_rules! async_command {
+    ($description:expr, $($key:ident : $value:expr,)* ( $($args:tt)* ) => $handler:expr $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut cmd = $crate::command!(@context async $description, ( $($args)* ) => $handler);
+        $( $crate::command!(@set cmd, $key, $value); )*
+        cmd
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::repl::SharedBuffer;
+    use crate::testing::fixtures::Calls;
+    use crate::{command, ReplContext};
+
+    fn builder<'a>(calls: &'a Calls) -> crate::repl::ReplBuilder<'a> {
+        #[rustfmt::skip]
+        let builder = Repl::builder()
+            .with_chaining(true)
+            .add("wait", async_command!("", (name: String) => |name: String| async move {
+                tokio::task::yield_now().await;
+                calls.push(name);
+                Ok(CommandStatus::Done)
+            }))
+            .add("fail", async_command!("", () => || async {
+                tokio::task::yield_now().await;
+                Err(anyhow::anyhow!("async failure"))
+            }))
+            .add("stop", async_command!("", () => || async { Ok(CommandStatus::Quit) }))
+            .add("sync", command!("", (name: String) => |name| {
+                calls.push(name);
+                Ok(CommandStatus::Done)
+            }))
+            .add("prompt", async_command!("", (ctx: &mut ReplContext) => |ctx: &mut ReplContext| {
+                ctx.set_prompt("async> ");
+                async { Ok(CommandStatus::Done) }
+            }));
+        builder
+    }
+
+    #[tokio::test]
+    async fn async_commands() {
+        let calls = Calls::default();
+        let output = SharedBuffer::default();
+        let input = "wait a\nsync b; wait c\nfail || wait d\nwait 1 2\nprompt\nstop\nwait e\n";
+        let mut repl = builder(&calls)
+            .out(Box::new(output.clone()) as Box<dyn Write>)
+            .line_source(input.as_bytes())
+            .build_async()
+            .unwrap();
+        repl.run().await.unwrap();
+        assert_eq!(calls.take(), ["a", "b", "c", "d"]);
+        let output = output.take();
+        assert!(output.contains("Error: async failure"));
+        assert!(output.contains("Usage:\n  wait name:String"));
+        assert_eq!(repl.repl().prompt, "async> ");
+    }
+
+    #[tokio::test]
+    async fn async_commands_in_chain() {
+        let calls = Calls::default();
+        let mut repl = builder(&calls)
+            .out(Box::new(std::io::sink()) as Box<dyn Write>)
+            .build_async()
+            .unwrap();
+        let result = repl.execute_line("fail && wait a; wait b").await.unwrap();
+        assert!(result.is_ok());
+        assert_eq!(calls.take(), ["b"]);
+        assert_eq!(
+            repl.execute_line("stop; wait c").await.unwrap(),
+            Ok(LoopStatus::Break)
+        );
+    }
+
+    #[tokio::test]
+    async fn cancelled_readline() {
+        let calls = Calls::default();
+        let mut repl = builder(&calls).build_async().unwrap();
+        let placeholder = ReplSource::Custom(Box::new(std::io::empty()));
+        let source = match std::mem::replace(&mut repl.repl.source, placeholder) {
+            ReplSource::Rustyline(source) => source,
+            ReplSource::Custom(_) => unreachable!(),
+        };
+        // simulate a line typed after the first `next` has been cancelled
+        let (send, recv) = std::sync::mpsc::channel::<()>();
+        repl.reading = Some(tokio::task::spawn_blocking(move || {
+            recv.recv().unwrap();
+            (source, Ok("sync a".to_string()))
+        }));
+        let timeout = std::time::Duration::from_millis(10);
+        assert!(tokio::time::timeout(timeout, repl.next()).await.is_err());
+        send.send(()).unwrap();
+        assert_eq!(repl.next().await.unwrap(), LoopStatus::Continue);
+        assert_eq!(calls.take(), ["a"]);
+        assert!(matches!(repl.repl.source, ReplSource::Rustyline(_)));
+    }
+
+    #[test]
+    fn async_commands_in_sync_repl() {
+        let calls = Calls::default();
+        let mut repl = builder(&calls)
+            .out(Box::new(std::io::sink()) as Box<dyn Write>)
+            .build()
+            .unwrap();
+        let err = "async command 'wait' cannot be executed here";
+        assert_eq!(repl.execute_line("wait a").unwrap().unwrap_err(), err);
+        assert!(repl.execute_line("wait a && sync b").unwrap().is_err());
+        assert!(repl.execute_line("wait a || sync c").unwrap().is_ok());
+        assert_eq!(calls.take(), ["c"]);
+    }
+}
@@ -0,0 +1,274 @@
+//! Configuration of built-in commands.
+
+use std::io::Write;
+
+use crate::command::ArgsError;
+
+/// Built-in command provided by the REPL itself.
+///
+/// Built-in commands can be renamed, given different descriptions, additional names
+/// or disabled using [`ReplBuilder::builtin`](crate::repl::ReplBuilder::builtin).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    /// Show help for all commands, a group or a single command
+    Help,
+    /// Quit the REPL
+    Quit,
+    /// Execute commands from a file, see [`Repl::run_file`](crate::Repl::run_file)
+    Source,
+    /// Set a variable, see [`ReplBuilder::with_variables`](crate::repl::ReplBuilder::with_variables)
+    Set,
+    /// Remove variables
+    Unset,
+    /// List variables
+    Vars,
+    /// Define or list aliases, see [`ReplBuilder::with_aliases`](crate::repl::ReplBuilder::with_aliases)
+    Alias,
+    /// Remove aliases
+    Unalias,
+    /// List background jobs, see [`ReplBuilder::with_jobs`](crate::repl::ReplBuilder::with_jobs)
+    Jobs,
+    /// Wait for background jobs
+    Wait,
+    /// Stop background jobs
+    Kill,
+    /// Print lines containing a pattern, see [`ReplBuilder::with_pipelines`](crate::repl::ReplBuilder::with_pipelines)
+    Grep,
+    /// Print the first lines
+    Head,
+    /// Print the last lines
+    Tail,
+    /// Count lines
+    Count,
+}
+
+impl Builtin {
+    /// All built-in commands in the order they are listed in help.
+    pub const ALL: &'static [Builtin] = &[
+        Builtin::Help,
+        Builtin::Quit,
+        Builtin::Source,
+        Builtin::Set,
+        Builtin::Unset,
+        Builtin::Vars,
+        Builtin::Alias,
+        Builtin::Unalias,
+        Builtin::Jobs,
+        Builtin::Wait,
+        Builtin::Kill,
+        Builtin::Grep,
+        Builtin::Head,
+        Builtin::Tail,
+        Builtin::Count,
+    ];
+
+    /// Name used if not configured otherwise.
+    pub fn default_name(&self) -> &'static str {
+        match self {
+            Builtin::Help => "help",
+            Builtin::Quit => "quit",
+            Builtin::Source => "source",
+            Builtin::Set => "set",
+            Builtin::Unset => "unset",
+            Builtin::Vars => "vars",
+            Builtin::Alias => "alias",
+            Builtin::Unalias => "unalias",
+            Builtin::Jobs => "jobs",
+            Builtin::Wait => "wait",
+            Builtin::Kill => "kill",
+            Builtin::Grep => "grep",
+            Builtin::Head => "head",
+            Builtin::Tail => "tail",
+            Builtin::Count => "count",
+        }
+    }
+
+    /// Description used if not configured otherwise.
+    pub fn default_description(&self) -> &'static str {
+        match self {
+            Builtin::Help => "Show this help message",
+            Builtin::Quit => "Quit repl",
+            Builtin::Source => "Execute commands from a file",
+            Builtin::Set => "Set variable: set NAME VALUE",
+            Builtin::Unset => "Remove variables: unset NAME...",
+            Builtin::Vars => "List all variables",
+            Builtin::Alias => "Define alias: alias NAME COMMAND..., or list aliases",
+            Builtin::Unalias => "Remove aliases: unalias NAME...",
+            Builtin::Jobs => "List background jobs",
+            Builtin::Wait => "Wait for background jobs: wait [ID...]",
+            Builtin::Kill => "Stop background jobs: kill ID...",
+            Builtin::Grep => "Print lines containing the pattern: grep [-i] [-v] PATTERN",
+            Builtin::Head => "Print the first lines: head [N]",
+            Builtin::Tail => "Print the last lines: tail [N]",
+            Builtin::Count => "Count lines",
+        }
+    }
+
+    /// Returns `true` for commands that read output of the previous command in a pipeline.
+    pub fn reads_input(&self) -> bool {
+        matches!(
+            self,
+            Builtin::Grep | Builtin::Head | Builtin::Tail | Builtin::Count
+        )
+    }
+}
+
+/// Execute a built-in command that [reads input](Builtin::reads_input).
+pub(crate) fn filter(
+    builtin: Builtin,
+    args: &[&str],
+    input: Option<&[u8]>,
+    out: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let input = input.ok_or_else(|| anyhow::anyhow!("no input, use after '|'"))?;
+    let input = String::from_utf8_lossy(input);
+    let lines: Vec<_> = input.lines().collect();
+    let selected = match builtin {
+        Builtin::Grep => {
+            let (mut ignore_case, mut invert, mut args) = (false, false, args);
+            while let [flag @ ("-i" | "-v"), rest @ ..] = args {
+                match *flag {
+                    "-i" => ignore_case = true,
+                    _ => invert = true,
+                }
+                args = rest;
+            }
+            let pattern = match args {
+                [pattern] if ignore_case => pattern.to_lowercase(),
+                [pattern] => pattern.to_string(),
+                _ => {
+                    let (got, expected) = (args.len(), 1);
+                    return Err(ArgsError::WrongNumberOfArguments { got, expected }.into());
+                }
+            };
+            let matches = |line: &&str| match ignore_case {
+                true => line.to_lowercase().contains(&pattern),
+                false => line.contains(&pattern),
+            };
+            lines
+                .into_iter()
+                .filter(|line| matches(line) != invert)
+                .collect()
+        }
+        Builtin::Head | Builtin::Tail => {
+            let n = match args {
+                [] => 10,
+                [n] => n.parse().map_err(|err| ArgsError::WrongArgumentValue {
+                    argument: n.to_string(),
+                    index: None,
+                    error: anyhow::Error::new(err),
+                })?,
+                _ => {
+                    let (got, max) = (args.len(), 1);
+                    return Err(ArgsError::TooManyArguments { got, max }.into());
+                }
+            };
+            let n = n.min(lines.len());
+            match builtin {
+                Builtin::Head => lines[..n].to_vec(),
+                _ => lines[lines.len() - n..].to_vec(),
+            }
+        }
+        Builtin::Count => {
+            if !args.is_empty() {
+                let (got, expected) = (args.len(), 0);
+                return Err(ArgsError::WrongNumberOfArguments { got, expected }.into());
+            }
+            writeln!(out, "{}", lines.len())?;
+            return Ok(());
+        }
+        _ => unreachable!("not a filter: {builtin:?}"),
+    };
+    for line in selected {
+        writeln!(out, "{line}")?;
+    }
+    Ok(())
+}
+
+/// Builder for a built-in command, see [`ReplBuilder::builtin`](crate::repl::ReplBuilder::builtin).
+#[derive(Debug, Clone)]
+pub struct BuiltinBuilder {
+    pub(crate) builtin: Builtin,
+    pub(crate) names: Vec<String>,
+    pub(crate) description: String,
+    pub(crate) enabled: bool,
+}
+
+impl BuiltinBuilder {
+    pub(crate) fn new(builtin: Builtin) -> Self {
+        BuiltinBuilder {
+            builtin,
+            names: vec![builtin.default_name().into()],
+            description: builtin.default_description().into(),
+            enabled: true,
+        }
+    }
+
+    /// Change the name of the command, e.g. `exit` instead of `quit`.
+    pub fn name(mut self, name: &str) -> Self {
+        self.names[0] = name.into();
+        self
+    }
+
+    /// Add an alternative name, e.g. `?` for `help`.
+    pub fn alias(mut self, name: &str) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    /// Change the description shown in help.
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Enable or disable the command. Disabled commands cannot be called and their names
+    /// are not reserved. Defaults to `true`.
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(builtin: Builtin, args: &[&str], input: &str) -> anyhow::Result<String> {
+        let mut out = vec![];
+        filter(builtin, args, Some(input.as_bytes()), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn filters() {
+        let input = "INFO start\nERROR disk\nWARN low\nerror net\n";
+        assert_eq!(
+            run(Builtin::Grep, &["ERROR"], input).unwrap(),
+            "ERROR disk\n"
+        );
+        assert_eq!(
+            run(Builtin::Grep, &["-i", "error"], input).unwrap(),
+            "ERROR disk\nerror net\n"
+        );
+        assert_eq!(
+            run(Builtin::Grep, &["-v", "-i", "error"], input).unwrap(),
+            "INFO start\nWARN low\n"
+        );
+        assert!(run(Builtin::Grep, &["-i"], input)
+            .unwrap_err()
+            .is::<ArgsError>());
+        assert_eq!(run(Builtin::Head, &["1"], input).unwrap(), "INFO start\n");
+        assert_eq!(
+            run(Builtin::Tail, &["2"], input).unwrap(),
+            "WARN low\nerror net\n"
+        );
+        assert_eq!(run(Builtin::Tail, &[], input).unwrap(), input);
+        assert!(run(Builtin::Head, &["x"], input).is_err());
+        assert_eq!(run(Builtin::Count, &[], input).unwrap(), "4\n");
+        assert_eq!(run(Builtin::Count, &[], "").unwrap(), "0\n");
+
+        let err = filter(Builtin::Count, &[], None, &mut vec![]).unwrap_err();
+        assert_eq!(err.to_string(), "no input, use after '|'");
+    }
+}
@@ -1,8 +1,14 @@
 //! Implementation of [`Command`]s with utilities that help to crate them.
 
+use std::any::{Any, TypeId};
+
 use anyhow;
 use thiserror;
 
+use crate::completion::ArgCompleters;
+use crate::context::{Change, ReplContext};
+use crate::jobs::JobContext;
+
 /// Command handler.
 ///
 /// It should return the status in case of correct execution. In case of
@@ -10,19 +16,44 @@ use thiserror;
 /// [`CriticalError`], which will be passed up from the REPL.
 ///
 /// The handler should validate command arguments and can return [`ArgsError`]
-/// to indicate that arguments were wrong.
-pub type Handler<'a> = dyn 'a + FnMut(&[&str]) -> anyhow::Result<CommandStatus>;
+/// to indicate that arguments were wrong. It receives a [`ReplContext`] which can
+/// be used to access the REPL that executes the command.
+pub type Handler<'a> =
+    dyn 'a + FnMut(&mut ReplContext<'_, 'a>, &[&str]) -> anyhow::Result<CommandStatus>;
 
 /// Single command that can be called in the REPL.
 ///
-/// Though it is possible to construct it by manually, it is not advised.
+/// Though it is possible to construct it manually with [`Command::new`] or [`Command::with_context`],
+/// it is not advised.
 /// One should rather use the provided [`command!`] macro which will generate
 /// appropriate arguments validation and `args_info` based on passed specification.
+#[non_exhaustive]
 pub struct Command<'a> {
     /// Command desctiption that will be displayed in the help message
     pub description: String,
     /// Names and types of arguments to the command
     pub args_info: Vec<String>,
+    /// Named options of the command, like `-t, --timeout <u64>`
+    pub options_info: Vec<String>,
+    /// Descriptions of arguments and options as pairs of an entry from `args_info`
+    /// or `options_info` and its description
+    pub arg_descriptions: Vec<(String, String)>,
+    /// Detailed description shown in the help for this command, may contain multiple
+    /// paragraphs separated with empty lines
+    pub long_description: String,
+    /// Examples of command usage shown in the help for this command
+    pub examples: Vec<String>,
+    /// Kinds of all arguments with optional completers of their values, used to complete
+    /// arguments when pressing TAB, may be left empty
+    pub arg_completers: ArgCompleters,
+    /// Whether the command can be executed in background by adding `&`, see [`job_command!`](crate::job_command!)
+    pub background: bool,
+    /// Whether the command reads output of the previous command in a pipeline,
+    /// see [`ReplContext::input`]
+    pub reads_input: bool,
+    /// Type of the REPL state taken by the handler, checked against the state set with
+    /// [`ReplBuilder::with_state`](crate::repl::ReplBuilder::with_state) when the command is added
+    pub state_type: Option<StateType>,
     /// Command handler which should validate arguments and perform command logic
     pub handler: Box<Handler<'a>>,
 }
@@ -83,29 +114,576 @@ where
 pub enum ArgsError {
     #[error("wrong number of arguments: got {got}, expected {expected}")]
     WrongNumberOfArguments { got: usize, expected: usize },
-    #[error("failed to parse argument value '{argument}': {error}")]
+    #[error("too few arguments: got {got}, expected at least {min}")]
+    TooFewArguments { got: usize, min: usize },
+    #[error("too many arguments: got {got}, expected at most {max}")]
+    TooManyArguments { got: usize, max: usize },
+    #[error("failed to parse argument value '{argument}'{}: {error}", fmt_index(.index))]
     WrongArgumentValue {
         argument: String,
+        /// Index of the element when parsing variadic argument
+        index: Option<usize>,
         #[source]
         error: anyhow::Error,
     },
+    #[error("unknown option '{option}'")]
+    UnknownOption { option: String },
+    #[error("missing value for option '{option}'")]
+    MissingOptionValue { option: String },
+    #[error("option '{option}' does not take a value")]
+    UnexpectedOptionValue { option: String },
+}
+
+/// Type of the REPL state required by a command, see [`Command::state_type`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateType {
+    id: TypeId,
+    name: &'static str,
+}
+
+impl StateType {
+    /// State of type `S`.
+    pub fn of<S: Any>() -> Self {
+        StateType {
+            id: TypeId::of::<S>(),
+            name: std::any::type_name::<S>(),
+        }
+    }
+
+    /// Returns `true` if `state` is of this type.
+    pub fn matches(&self, state: &dyn Any) -> bool {
+        state.type_id() == self.id
+    }
+
+    /// Name of the type, only meant for diagnostics.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+fn fmt_index(index: &Option<usize>) -> String {
+    index.map_or_else(String::new, |i| format!(" (element {i})"))
 }
 
 impl<'a> Command<'a> {
+    /// Create a command with given handler, which has to validate the arguments itself.
+    ///
+    /// All the other fields are empty and can be set afterwards:
+    /// ```rust
+    /// # use easy_repl::{Command, CommandStatus};
+    /// let mut cmd = Command::new("Say hello", |args| {
+    ///     println!("Hello {}!", args.join(" "));
+    ///     Ok(CommandStatus::Done)
+    /// });
+    /// cmd.args_info = vec!["names:String...".into()];
+    /// assert!(cmd.run(&["world"]).is_ok());
+    /// ```
+    ///
+    /// Use [`Command::with_context`] if the handler needs the [`ReplContext`].
+    pub fn new<F>(description: impl Into<String>, mut handler: F) -> Self
+    where
+        F: 'a + FnMut(&[&str]) -> anyhow::Result<CommandStatus>,
+    {
+        Command::with_context(description, move |_ctx, args| handler(args))
+    }
+
+    /// Create a command with a handler that receives the [`ReplContext`], see [`Command::new`].
+    /// ```rust
+    /// # use easy_repl::{Command, CommandStatus};
+    /// let mut cmd = Command::with_context("Say hello", |ctx, args| {
+    ///     writeln!(ctx.out(), "Hello {}!", args.join(" "))?;
+    ///     Ok(CommandStatus::Done)
+    /// });
+    /// assert!(cmd.run(&["world"]).is_ok());
+    /// ```
+    pub fn with_context<F>(description: impl Into<String>, handler: F) -> Self
+    where
+        F: 'a + FnMut(&mut ReplContext<'_, 'a>, &[&str]) -> anyhow::Result<CommandStatus>,
+    {
+        Command {
+            description: description.into(),
+            args_info: vec![],
+            options_info: vec![],
+            arg_descriptions: vec![],
+            long_description: String::new(),
+            examples: vec![],
+            arg_completers: vec![],
+            background: false,
+            reads_input: false,
+            state_type: None,
+            handler: Box::new(handler),
+        }
+    }
+
+    /// Create a command with arguments parsed into a type implementing [`ReplArgs`].
+    ///
+    /// This is an alternative to the [`command!`] macro, useful for commands with many arguments,
+    /// usually used along with `#[derive(ReplArgs)]`:
+    #[cfg_attr(feature = "derive", doc = "```rust")]
+    #[cfg_attr(not(feature = "derive"), doc = "```ignore")]
+    /// # use easy_repl::{Command, CommandStatus, ReplArgs};
+    /// #[derive(ReplArgs)]
+    /// struct ConnectArgs {
+    ///     /// Host to connect to
+    ///     host: String,
+    ///     /// Port number
+    ///     #[repl(default = 8080)]
+    ///     port: u16,
+    ///     /// Print more information
+    ///     #[repl(flag, short = 'v')]
+    ///     verbose: bool,
+    /// }
+    ///
+    /// let mut cmd = Command::from_args("Connect to a host", |args: ConnectArgs| {
+    ///     println!("Connecting to {}:{}", args.host, args.port);
+    ///     Ok(CommandStatus::Done)
+    /// });
+    /// assert_eq!(cmd.args_info, &["host:String", "[port:u16=8080]"]);
+    /// assert_eq!(cmd.options_info, &["-v, --verbose"]);
+    /// assert!(cmd.run(&["localhost", "-v"]).is_ok());
+    /// ```
+    pub fn from_args<A, F>(description: impl Into<String>, mut handler: F) -> Self
+    where
+        A: ReplArgs,
+        F: 'a + FnMut(A) -> anyhow::Result<CommandStatus>,
+    {
+        let mut cmd = Command::new(description, move |args| handler(A::parse(args)?));
+        cmd.args_info = A::args_info();
+        cmd.options_info = A::options_info();
+        cmd.arg_descriptions = A::arg_descriptions();
+        cmd.arg_completers = A::arg_completers();
+        cmd
+    }
+
     /// Validate the arguments and invoke the handler if arguments are correct.
+    ///
+    /// This runs the command outside of any REPL, so the [`ReplContext`] passed to the handler
+    /// prints to [`std::io::Stderr`] and any changes made through it are discarded.
+    /// The [`Job`](crate::jobs::Job) of a command created with [`job_command!`](crate::job_command!)
+    /// is executed in the current thread.
     pub fn run(&mut self, args: &[&str]) -> anyhow::Result<CommandStatus> {
-        (self.handler)(args)
+        let mut prompt = String::new();
+        let mut out = std::io::stderr();
+        let mut ctx = ReplContext::new(
+            &mut prompt,
+            &mut out,
+            None,
+            None,
+            Default::default(),
+            None,
+            None,
+        );
+        let status = self.run_in(&mut ctx, args)?;
+        for change in ctx.into_changes() {
+            if let Change::Job(job) = change {
+                return job(&mut JobContext::new(&mut out, Default::default(), None));
+            }
+        }
+        Ok(status)
+    }
+
+    /// Same as [`Command::run`] but with the given [`ReplContext`].
+    pub fn run_in(
+        &mut self,
+        ctx: &mut ReplContext<'_, 'a>,
+        args: &[&str],
+    ) -> anyhow::Result<CommandStatus> {
+        (self.handler)(ctx, args)
     }
 
     /// Returns the string description of the argument types
     pub fn arg_types(&self) -> Vec<&str> {
         self.args_info
             .iter()
-            .map(|info| info.split(':').collect::<Vec<_>>()[1])
+            .map(|info| {
+                let info = info.trim_start_matches('[').trim_end_matches(']');
+                let ty = info.split_once(':').map_or(info, |(_, ty)| ty);
+                ty.split_once('=').map_or(ty, |(ty, _)| ty)
+            })
             .collect()
     }
 }
 
+/// Kind of an argument (used by [`command!`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArgKind {
+    /// Argument that must always be given
+    Required,
+    /// Argument that can be omitted, only allowed after all the required ones
+    Optional,
+    /// Argument that takes all the remaining values, only allowed as the last one
+    Variadic {
+        /// Minimal number of values
+        min: usize,
+        /// Maximal number of values, [`None`] if unbounded
+        max: Option<usize>,
+    },
+    /// Boolean flag given by name, e.g. `--verbose` or `-v`
+    Flag {
+        /// Long name used as `--long`
+        long: &'static str,
+        /// Optional short name used as `-s`
+        short: Option<&'static str>,
+    },
+    /// Named option that takes a value, e.g. `--timeout 30` or `--timeout=30`
+    Named {
+        /// Long name used as `--long`
+        long: &'static str,
+        /// Optional short name used as `-s`
+        short: Option<&'static str>,
+    },
+}
+
+impl ArgKind {
+    /// Create [`ArgKind::Variadic`] with bounds on the number of values given as a range.
+    pub fn variadic<R: std::ops::RangeBounds<usize>>(range: R) -> Self {
+        use std::ops::Bound;
+        let min = match range.start_bound() {
+            Bound::Included(n) => *n,
+            Bound::Excluded(n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let max = match range.end_bound() {
+            Bound::Included(n) => Some(*n),
+            Bound::Excluded(n) => Some(n.saturating_sub(1)),
+            Bound::Unbounded => None,
+        };
+        ArgKind::Variadic { min, max }
+    }
+
+    /// Check if this is a positional argument (as opposed to flags and named options).
+    pub const fn is_positional(&self) -> bool {
+        !matches!(self, ArgKind::Flag { .. } | ArgKind::Named { .. })
+    }
+
+    pub(crate) fn matches_long(&self, name: &str) -> bool {
+        matches!(self, ArgKind::Flag { long, .. } | ArgKind::Named { long, .. } if *long == name)
+    }
+
+    pub(crate) fn matches_short(&self, name: &str) -> bool {
+        matches!(self, ArgKind::Flag { short: Some(short), .. } | ArgKind::Named { short: Some(short), .. } if *short == name)
+    }
+}
+
+/// Assign command arguments to argument slots of given kinds (used by [`command!`]).
+///
+/// Flags and named options may appear anywhere among the positional arguments,
+/// unless they come after `--`, which makes all the following arguments positional.
+/// Words starting with `-` that do not match any short option name are treated as
+/// positional, so e.g. negative numbers can be passed without `--`.
+///
+/// Returns values for each of the `kinds`: [`ArgKind::Required`] always get a single
+/// value, [`ArgKind::Optional`] get zero or one (never a value needed by a required argument)
+/// and [`ArgKind::Variadic`] get all the remaining values. [`ArgKind::Flag`] and [`ArgKind::Named`] get the last given value,
+/// if any. Fails if the number of arguments is wrong or an option is invalid.
+pub fn assign_args<'s>(
+    kinds: &[ArgKind],
+    args: &[&'s str],
+) -> Result<Vec<Vec<&'s str>>, ArgsError> {
+    let mut values = vec![Vec::new(); kinds.len()];
+
+    let mut positional = Vec::with_capacity(args.len());
+    let mut options_end = false;
+    let mut args = args.iter().copied();
+    while let Some(arg) = args.next() {
+        let (found, value) = if options_end {
+            (None, None)
+        } else if arg == "--" {
+            options_end = true;
+            continue;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (long, value) = long
+                .split_once('=')
+                .map_or((long, None), |(long, value)| (long, Some(value)));
+            match kinds.iter().position(|k| k.matches_long(long)) {
+                Some(i) => (Some(i), value),
+                None => return Err(ArgsError::UnknownOption { option: arg.into() }),
+            }
+        } else if let Some(short) = arg.strip_prefix('-') {
+            (kinds.iter().position(|k| k.matches_short(short)), None)
+        } else {
+            (None, None)
+        };
+        let option = || arg.split('=').next().unwrap_or(arg).to_string();
+        match found.map(|i| (i, &kinds[i])) {
+            None => positional.push(arg),
+            Some((i, ArgKind::Flag { .. })) => match value {
+                None => values[i] = vec![arg],
+                Some(_) => return Err(ArgsError::UnexpectedOptionValue { option: option() }),
+            },
+            Some((i, _)) => match value.or_else(|| args.next()) {
+                Some(value) => values[i] = vec![value],
+                None => return Err(ArgsError::MissingOptionValue { option: option() }),
+            },
+        }
+    }
+
+    let (mut min, mut max) = (0, Some(0));
+    for kind in kinds {
+        match kind {
+            ArgKind::Required => {
+                min += 1;
+                max = max.map(|n| n + 1);
+            }
+            ArgKind::Optional => max = max.map(|n| n + 1),
+            ArgKind::Variadic {
+                min: vmin,
+                max: vmax,
+            } => {
+                min += vmin;
+                max = max.zip(*vmax).map(|(n, vmax)| n + vmax);
+            }
+            ArgKind::Flag { .. } | ArgKind::Named { .. } => {}
+        }
+    }
+    let got = positional.len();
+    match max {
+        Some(max) if min == max && got != max => {
+            return Err(ArgsError::WrongNumberOfArguments { got, expected: max })
+        }
+        Some(max) if got > max => return Err(ArgsError::TooManyArguments { got, max }),
+        _ if got < min => return Err(ArgsError::TooFewArguments { got, min }),
+        _ => {}
+    }
+
+    // optional and variadic arguments only take values that are not needed by the required ones
+    let mut spare = got - min;
+    let mut positional = positional.into_iter();
+    for (kind, value) in kinds.iter().zip(values.iter_mut()) {
+        match kind {
+            ArgKind::Required => value.extend(positional.next()),
+            ArgKind::Optional if spare > 0 => {
+                value.extend(positional.next());
+                spare -= 1;
+            }
+            ArgKind::Optional => {}
+            ArgKind::Variadic { min, max } => {
+                let extra = max.map_or(spare, |max| spare.min(max.saturating_sub(*min)));
+                value.extend(positional.by_ref().take(min + extra));
+                spare -= extra;
+            }
+            ArgKind::Flag { .. } | ArgKind::Named { .. } => {}
+        }
+    }
+    Ok(values)
+}
+
+/// Check that all required arguments come before the optional ones and that a variadic
+/// argument is the last positional one, used by [`command!`] to reject other commands at
+/// compile time.
+#[doc(hidden)]
+pub const fn check_arg_order(kinds: &[ArgKind]) {
+    let (mut optional, mut variadic) = (false, false);
+    let mut i = 0;
+    while i < kinds.len() {
+        match kinds[i] {
+            kind if variadic && kind.is_positional() => {
+                panic!("variadic argument must be the last positional argument")
+            }
+            ArgKind::Required if optional => {
+                panic!("required arguments must come before optional ones")
+            }
+            ArgKind::Optional => optional = true,
+            ArgKind::Variadic { .. } => variadic = true,
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Command arguments that can be parsed from the REPL input, used with [`Command::from_args`].
+///
+/// This should usually be implemented using `#[derive(ReplArgs)]` (requires the `derive` feature),
+/// which will generate an implementation consistent with the [`command!`] macro. Each struct field
+/// is a single argument, with types and attributes corresponding to the [`command!`] syntax:
+///
+/// | [`command!`]               | `#[derive(ReplArgs)]`                                    |
+/// |----------------------------|----------------------------------------------------------|
+/// | `name: T`                  | `name: T`                                                |
+/// | `name: T = default`        | `#[repl(default = default)] name: T`                     |
+/// | `name: Option<T>`          | `name: Option<T>`                                        |
+/// | `name: Vec<T> [1..]`       | `#[repl(count = 1..)] name: Vec<T>`                      |
+/// | `--name\|-n`               | `#[repl(flag, short = 'n')] name: bool`                  |
+/// | `--name: T`                | `#[repl(option)] name: Option<T>`                        |
+/// | `--name: T = default`      | `#[repl(option, default = default)] name: T`             |
+///
+/// Doc comments of the fields are used as argument descriptions.
+pub trait ReplArgs: Sized {
+    /// Kinds of arguments, in the same order as values passed to [`ReplArgs::from_values`]
+    fn arg_kinds() -> Vec<ArgKind>;
+    /// Names and types of positional arguments, see [`Command::args_info`]
+    fn args_info() -> Vec<String>;
+    /// Named options, see [`Command::options_info`]
+    fn options_info() -> Vec<String>;
+    /// Descriptions of arguments, see [`Command::arg_descriptions`]
+    fn arg_descriptions() -> Vec<(String, String)> {
+        Vec::new()
+    }
+    /// Argument kinds with completers of their values, see [`Command::arg_completers`]
+    fn arg_completers() -> ArgCompleters {
+        Self::arg_kinds()
+            .into_iter()
+            .map(|kind| (kind, None))
+            .collect()
+    }
+    /// Construct from argument values as returned by [`assign_args`]
+    fn from_values(values: &[Vec<&str>]) -> Result<Self, ArgsError>;
+
+    /// Parse command arguments
+    fn parse(args: &[&str]) -> Result<Self, ArgsError> {
+        let values = assign_args(&Self::arg_kinds(), args)?;
+        Self::from_values(&values)
+    }
+}
+
+/// Join doc comments of arguments into `arg_descriptions`, used by [`command!`].
+#[doc(hidden)]
+pub fn describe_args(info: &[(bool, String)], docs: &[&[&str]]) -> Vec<(String, String)> {
+    info.iter()
+        .zip(docs)
+        .filter(|(_, lines)| !lines.is_empty())
+        .map(|((_, info), lines)| {
+            let lines: Vec<_> = lines.iter().map(|line| line.trim()).collect();
+            (info.to_string(), lines.join(" ").trim().to_string())
+        })
+        .collect()
+}
+
+/// Parse a single argument value, converting errors to [`ArgsError`] (used by [`command!`]).
+pub fn parse_arg<T>(arg: &str) -> Result<T, ArgsError>
+where
+    T: std::str::FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    arg.parse::<T>()
+        .map_err(|err| ArgsError::WrongArgumentValue {
+            argument: arg.into(),
+            index: None,
+            error: err.into(),
+        })
+}
+
+/// Parse all values of a variadic argument (used by [`command!`]).
+pub fn parse_variadic<T>(args: &[&str]) -> Result<Vec<T>, ArgsError>
+where
+    T: std::str::FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    args.iter()
+        .enumerate()
+        .map(|(i, arg)| {
+            arg.parse::<T>()
+                .map_err(|err| ArgsError::WrongArgumentValue {
+                    argument: (*arg).into(),
+                    index: Some(i),
+                    error: err.into(),
+                })
+        })
+        .collect()
+}
+
+/// Argument type with a fixed set of values, usually an enum.
+///
+/// Values can be given by name or by any unambiguous prefix of the name. Types implementing
+/// this trait are shown in help as a list of choices, e.g. `mode:{fast|safe|debug}`, and
+/// are completed with their names if they also implement [`Completable`].
+/// All of this is implemented by `#[derive(Choice)]` for enums with unit variants:
+#[cfg_attr(feature = "derive", doc = "```rust")]
+#[cfg_attr(not(feature = "derive"), doc = "```ignore")]
+/// # use easy_repl::{CommandStatus, Choice, command};
+/// #[derive(Choice, Debug, PartialEq)]
+/// enum Mode {
+///     Fast,
+///     Safe,
+///     /// Names can be changed, by default these are kebab-case variant names
+///     #[repl(name = "dbg")]
+///     Debug,
+/// }
+///
+/// let mut cmd = command! {
+///     "Set the mode",
+///     (mode: Mode) => |mode| {
+///         Ok(CommandStatus::Done)
+///     }
+/// };
+/// assert_eq!(cmd.args_info, &["mode:{fast|safe|dbg}"]);
+/// assert_eq!("s".parse::<Mode>().unwrap(), Mode::Safe);
+/// assert!(cmd.run(&["d"]).is_ok());
+/// let err = cmd.run(&["x"]).unwrap_err();
+/// assert_eq!(err.to_string(), "failed to parse argument value 'x': expected one of: fast, safe, dbg");
+/// ```
+///
+/// [`Completable`]: crate::completion::Completable
+pub trait Choice: Sized {
+    /// Names of all the values
+    fn choices() -> &'static [&'static str];
+    /// Create the value with the name at given index in [`Choice::choices`]
+    fn from_choice(index: usize) -> Self;
+
+    /// Parse the value from its name or an unambiguous prefix of the name
+    fn parse_choice(value: &str) -> Result<Self, ChoiceError> {
+        let choices = Self::choices();
+        if let Some(i) = choices.iter().position(|c| *c == value) {
+            return Ok(Self::from_choice(i));
+        }
+        let matching: Vec<_> = (0..choices.len())
+            .filter(|i| !value.is_empty() && choices[*i].starts_with(value))
+            .collect();
+        match matching.as_slice() {
+            [i] => Ok(Self::from_choice(*i)),
+            [] => Err(ChoiceError::Invalid { choices }),
+            _ => Err(ChoiceError::Ambiguous {
+                matching: matching.iter().map(|i| choices[*i]).collect(),
+            }),
+        }
+    }
+}
+
+/// Error when parsing a [`Choice`].
+#[allow(missing_docs)]
+#[derive(Debug, thiserror::Error)]
+pub enum ChoiceError {
+    #[error("expected one of: {}", .choices.join(", "))]
+    Invalid { choices: &'static [&'static str] },
+    #[error("ambiguous value, matches: {}", .matching.join(", "))]
+    Ambiguous { matching: Vec<&'static str> },
+}
+
+/// Selects how argument type is shown in help, used by [`command!`].
+///
+/// Uses autoref-based specialization, see [`TypeCompleter`](crate::completion::TypeCompleter).
+#[doc(hidden)]
+pub struct TypeInfo<T>(std::marker::PhantomData<T>);
+
+impl<T> TypeInfo<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        TypeInfo(std::marker::PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait ChoiceTypeInfo {
+    fn type_info(&self, name: &str) -> String;
+}
+
+impl<T: Choice> ChoiceTypeInfo for TypeInfo<T> {
+    fn type_info(&self, _name: &str) -> String {
+        format!("{{{}}}", T::choices().join("|"))
+    }
+}
+
+#[doc(hidden)]
+pub trait OtherTypeInfo {
+    fn type_info(&self, name: &str) -> String;
+}
+
+impl<T> OtherTypeInfo for &TypeInfo<T> {
+    fn type_info(&self, name: &str) -> String {
+        name.to_string()
+    }
+}
+
 impl<'a> std::fmt::Debug for Command<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Command")
@@ -114,12 +692,12 @@ impl<'a> std::fmt::Debug for Command<'a> {
     }
 }
 
-/// Generate argument validator based on a list of types (used by [`command!`]).
+/// Generate argument validator based on a list of types.
 ///
 /// This macro can be used to generate a closure that takes arguments as `&[&str]`
 /// and makes sure that the nubmer of arguments is correct and all can be parsed
 /// to appropriate types. This macro should generally not be used. Prefer to use
-/// [`command!`] which will use this macro appropriately.
+/// [`command!`] which also supports optional and variadic arguments.
 ///
 /// Example usage:
 /// ```rust
@@ -151,6 +729,7 @@ macro_rules! validator {
                     if let Err(err) = args[i].parse::<$type>() {
                         return Err($crate::command::ArgsError::WrongArgumentValue {
                             argument: args[i].into(),
+                            index: None,
                             error: err.into()
                     });
                     }
@@ -165,7 +744,6 @@ macro_rules! validator {
     (@replace $_old:tt $new:expr) => { $new };
 }
 
-// TODO: avoid parsing arguments 2 times by generating validation logic in the function
 /// Generate [`Command`] based on desctiption, list of arg types and a closure used in handler.
 ///
 /// This macro should be used when creating [`Command`]s. It takes a string description,
@@ -188,54 +766,428 @@ macro_rules! validator {
 /// will roughly be translated into something like (code here is slightly simplified):
 /// ```rust
 /// # use anyhow;
-/// # use easy_repl::{Command, CommandStatus, command, validator};
-/// let cmd = Command {
-///     description: "Example command".into(),
-///     args_info: vec!["arg1:i32".into(), "arg2:String".into()],
-///     handler: Box::new(move |args| -> anyhow::Result<CommandStatus> {
-///         let validator = validator!(i32, String);
-///         validator(args)?;
-///         let mut handler = |arg1, arg2| {
-///             Ok(CommandStatus::Done)
-///         };
-///         handler(args[0].parse::<i32>().unwrap(), args[1].parse::<String>().unwrap())
-///     }),
+/// # use easy_repl::{Command, CommandStatus, command};
+/// # use easy_repl::command::{assign_args, parse_arg, ArgKind};
+/// let mut cmd = Command::new("Example command", move |args| -> anyhow::Result<CommandStatus> {
+///     let values = assign_args(&[ArgKind::Required, ArgKind::Required], args)?;
+///     let mut handler = |arg1, arg2| {
+///         Ok(CommandStatus::Done)
+///     };
+///     handler(parse_arg::<i32>(values[0][0])?, parse_arg::<String>(values[1][0])?)
+/// });
+/// cmd.args_info = vec!["arg1:i32".into(), "arg2:String".into()];
+/// cmd.arg_completers = vec![(ArgKind::Required, None), (ArgKind::Required, None)];
+/// ```
+///
+/// # Optional arguments
+///
+/// Arguments at the end of the list can be made optional, so that they can be omitted
+/// when calling the command. An argument in the form `name: type = default` will receive
+/// the `default` value if omitted, and an argument `name: Option<type>` will receive [`None`]:
+/// ```rust
+/// # use easy_repl::{CommandStatus, command};
+/// let mut cmd = command! {
+///     "Connect to a host",
+///     (host: String, port: u16 = 8080, timeout: Option<u64>) => |host, port, timeout| {
+///         Ok(CommandStatus::Done)
+///     }
+/// };
+/// assert_eq!(cmd.args_info, &["host:String", "[port:u16=8080]", "[timeout:u64]"]);
+/// assert!(cmd.run(&["localhost"]).is_ok());
+/// assert!(cmd.run(&["localhost", "80", "10"]).is_ok());
+/// ```
+///
+/// Optional arguments must come after all the required ones, otherwise the command
+/// does not compile:
+/// ```compile_fail
+/// # use easy_repl::{CommandStatus, command};
+/// let cmd = command! {
+///     "Connect to a host",
+///     (port: Option<u16>, host: String) => |port, host| Ok(CommandStatus::Done)
+/// };
+/// ```
+///
+/// # Variadic arguments
+///
+/// The last positional argument can be given as `name: Vec<type>`, in which case it will
+/// receive all the remaining values, each parsed separately. By default any number of values
+/// is accepted, which can be limited by specifying a range in brackets after the type:
+/// ```rust
+/// # use easy_repl::{CommandStatus, command};
+/// let mut cmd = command! {
+///     "Send bytes to a device",
+///     (device: String, bytes: Vec<u8> [1..=4]) => |device, bytes: Vec<u8>| {
+///         Ok(CommandStatus::Done)
+///     }
+/// };
+/// assert_eq!(cmd.args_info, &["device:String", "bytes:u8..."]);
+/// assert!(cmd.run(&["uart", "1", "2", "3"]).is_ok());
+/// assert!(cmd.run(&["uart"]).is_err());
+/// ```
+///
+/// A variadic argument that is not the last positional one does not compile:
+/// ```compile_fail
+/// # use easy_repl::{CommandStatus, command};
+/// let cmd = command! {
+///     "Send bytes to a device",
+///     (bytes: Vec<u8>, device: String) => |bytes: Vec<u8>, device| Ok(CommandStatus::Done)
+/// };
+/// ```
+///
+/// # Flags and named options
+///
+/// Besides positional arguments, a command can take boolean flags in the form `--name`
+/// and named options that take a value, in the form `--name: type`. Both can be given
+/// a short alias, e.g. `--name|-n`. Flags will be passed to the handler as [`bool`], named
+/// options as [`Option`], unless a default value is specified with `--name: type = default`.
+/// When calling the command, these can be placed anywhere among positional arguments:
+/// ```rust
+/// # use easy_repl::{CommandStatus, command};
+/// let mut cmd = command! {
+///     "Connect to a host",
+///     (host: String, --verbose|-v, --timeout|-t: u64 = 30, --retries: u32)
+///         => |host, verbose, timeout, retries: Option<u32>| {
+///         Ok(CommandStatus::Done)
+///     }
+/// };
+/// assert_eq!(cmd.args_info, &["host:String"]);
+/// assert_eq!(cmd.options_info, &["-v, --verbose", "-t, --timeout <u64=30>", "--retries <u32>"]);
+/// assert!(cmd.run(&["-v", "localhost", "--timeout", "10"]).is_ok());
+/// assert!(cmd.run(&["localhost", "--retries=3"]).is_ok());
+/// assert!(cmd.run(&["localhost", "--unknown"]).is_err());
+/// ```
+///
+/// # Help information
+///
+/// Arguments can be described with doc comments, and a long description and usage
+/// examples can be given before the argument list. All of these are optional and are
+/// shown by `help <command>`:
+/// ```rust
+/// # use easy_repl::{CommandStatus, command};
+/// let cmd = command! {
+///     "Connect to a host",
+///     long: "Opens a TCP connection to the host.\n\nThe connection is kept open until `disconnect`.",
+///     examples: ["connect localhost", "connect -v example.com 8080"],
+///     (
+///         /// Host name or IP address
+///         host: String,
+///         /// Port number
+///         port: u16 = 80,
+///         /// Print more information
+///         --verbose|-v,
+///     ) => |host, port, verbose| {
+///         Ok(CommandStatus::Done)
+///     }
 /// };
+/// assert_eq!(cmd.arg_descriptions[0], ("host:String".into(), "Host name or IP address".into()));
+/// assert_eq!(cmd.examples.len(), 2);
+/// ```
+///
+/// # Completion
+///
+/// Values of arguments are completed when pressing TAB. The completer can be specified
+/// with `#[complete(expr)]` before an argument, where `expr` implements [`ArgCompleter`],
+/// e.g. a list of values or a closure. Arguments without it are completed based on their
+/// type if it implements [`Completable`], e.g. file names for [`PathBuf`](std::path::PathBuf)
+/// and `true`/`false` for [`bool`]:
+/// ```rust
+/// # use std::path::PathBuf;
+/// # use easy_repl::{CommandStatus, command};
+/// let cmd = command! {
+///     "Save the log",
+///     (
+///         path: PathBuf,
+///         #[complete(["text", "json"])]
+///         --format: String = "text".to_string(),
+///     ) => |path, format| {
+///         Ok(CommandStatus::Done)
+///     }
+/// };
+/// let format = cmd.arg_completers[1].1.as_ref().unwrap();
+/// assert_eq!(format.complete("j"), &["json"]);
+/// ```
+///
+/// [`ArgCompleter`]: crate::completion::ArgCompleter
+/// [`Completable`]: crate::completion::Completable
+///
+/// # Access to the REPL
+///
+/// If the first argument is given as `name: &mut ReplContext`, the handler will receive
+/// [`ReplContext`] as its first parameter, which can be used to modify the REPL, e.g.
+/// change the prompt or add new commands. This parameter has to be annotated in the closure:
+/// ```rust
+/// # use easy_repl::{CommandStatus, ReplContext, command};
+/// let mut cmd = command! {
+///     "Switch to a device",
+///     (ctx: &mut ReplContext, device: String) => |ctx: &mut ReplContext, device: String| {
+///         ctx.set_prompt(format!("{}> ", device));
+///         Ok(CommandStatus::Done)
+///     }
+/// };
+/// assert_eq!(cmd.args_info, &["device:String"]);
+/// ```
+///
+/// Similarly, if the REPL has been created with [`ReplBuilder::with_state`](crate::repl::ReplBuilder::with_state),
+/// then an argument `name: &mut S` (first one, or right after [`ReplContext`]) will pass the
+/// state to the handler. Adding the command to a REPL fails with [`BuilderError::StateType`](crate::repl::BuilderError::StateType)
+/// if there is no state or it is not of type `S`.
+/// ```rust
+/// # use easy_repl::{CommandStatus, ReplContext, command};
+/// struct Device {
+///     name: String,
+/// }
+///
+/// let cmd = command! {
+///     "Rename the device",
+///     (ctx: &mut ReplContext, device: &mut Device, name: String)
+///         => |ctx: &mut ReplContext, device: &mut Device, name: String| {
+///         device.name = name;
+///         ctx.set_prompt(format!("{}> ", device.name));
+///         Ok(CommandStatus::Done)
+///     }
+/// };
+/// assert_eq!(cmd.args_info, &["name:String"]);
+/// ```
+///
+/// # Reading input
+///
+/// When [`ReplBuilder::with_pipelines`](crate::repl::ReplBuilder::with_pipelines) is enabled,
+/// output of a command can be passed to another one, e.g. `logs | upper`. Only commands
+/// declared with `reads_input: true` can be used after `|`, they read the output of the previous
+/// command from [`ReplContext::input`]:
+/// ```rust
+/// # use std::io::Read;
+/// # use easy_repl::{CommandStatus, ReplContext, command};
+/// let cmd = command! {
+///     "Print the input in upper case",
+///     reads_input: true,
+///     (ctx: &mut ReplContext) => |ctx: &mut ReplContext| {
+///         let mut text = String::new();
+///         if let Some(input) = ctx.input() {
+///             input.read_to_string(&mut text)?;
+///         }
+///         write!(ctx.out(), "{}", text.to_uppercase())?;
+///         Ok(CommandStatus::Done)
+///     }
+/// };
+/// assert!(cmd.reads_input);
 /// ```
 #[macro_export]
 macro_rules! command {
-    ($description:expr, ( $($( $name:ident )? : $type:ty),* ) => $handler:expr $(,)?) => {
-        $crate::command::Command {
-            description: $description.into(),
-            args_info: vec![ $(
-                concat!($(stringify!($name), )? ":", stringify!($type)).into()
-            ),* ], // TODO
-            handler: command!(@handler $($type)*, $handler),
-        }
+    ($description:expr, $($key:ident : $value:expr,)* ( $($args:tt)* ) => $handler:expr $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut cmd = $crate::command!(@context sync $description, ( $($args)* ) => $handler);
+        $( $crate::command!(@set cmd, $key, $value); )*
+        cmd
+    }};
+    // additional help information
+    (@set $cmd:ident, long, $value:expr) => {
+        $cmd.long_description = $value.into();
     };
-    (@handler $($type:ty)*, $handler:expr) => {
-        Box::new( move |#[allow(unused_variables)] args| -> $crate::anyhow::Result<CommandStatus> {
-            let validator = $crate::validator!($($type),*);
-            validator(args)?;
-            #[allow(unused_mut)]
-            let mut handler = $handler;
-            command!(@handler_call handler; args; $($type;)*)
-        })
+    (@set $cmd:ident, examples, $value:expr) => {
+        $cmd.examples = $value.into_iter().map(Into::into).collect();
+    };
+    // output of the previous command in a pipeline
+    (@set $cmd:ident, reads_input, $value:expr) => {
+        $cmd.reads_input = $value;
+    };
+    // `sync`, `async` or `job` handler, see `async_command!` and `job_command!`
+    (@context $mode:tt $description:expr, ( $ctx:ident : &mut ReplContext $(, $($args:tt)*)? ) => $handler:expr) => {
+        $crate::command!(@state [$mode, $description, $handler, $ctx, [$ctx]] $($($args)*)?)
+    };
+    (@context $mode:tt $description:expr, ( $($args:tt)* ) => $handler:expr) => {
+        $crate::command!(@state [$mode, $description, $handler, _ctx, []] $($args)*)
+    };
+    // optional application state, must be before all the other arguments
+    (@state [$($info:tt)*] $state:ident : &mut $stype:ty $(, $($args:tt)*)?) => {
+        $crate::command!(@parse [$($info)*, [$state $stype]] [] [] [] $($($args)*)?)
+    };
+    (@state [$($info:tt)*] $($args:tt)*) => {
+        $crate::command!(@parse [$($info)*, []] [] [] [] $($args)*)
+    };
+    // collect doc comments (or `#[doc]` attributes) describing the next argument
+    (@parse $info:tt $acc:tt [$($docs:expr),*] $compl:tt #[doc = $doc:expr] $($rest:tt)*) => {
+        $crate::command!(@parse $info $acc [$($docs,)* $doc] $compl $($rest)*)
+    };
+    // completer of the next argument's values
+    (@parse $info:tt $acc:tt $docs:tt [] #[complete($compl:expr)] $($rest:tt)*) => {
+        $crate::command!(@parse $info $acc $docs [$compl] $($rest)*)
+    };
+    // parse the list of arguments into normalized entries; named option with a default value
+    (@parse $info:tt [$($acc:tt)*] $docs:tt $compl:tt - - $name:ident $(| - $short:ident)? : $type:ty = $default:expr $(, $($rest:tt)*)?) => {
+        $crate::command!(@parse $info [$($acc)* ($docs $compl named_default [$name] [$($short)?] $type, $default)] [] [] $($($rest)*)?)
+    };
+    // named option
+    (@parse $info:tt [$($acc:tt)*] $docs:tt $compl:tt - - $name:ident $(| - $short:ident)? : $type:ty $(, $($rest:tt)*)?) => {
+        $crate::command!(@parse $info [$($acc)* ($docs $compl named [$name] [$($short)?] $type)] [] [] $($($rest)*)?)
+    };
+    // boolean flag
+    (@parse $info:tt [$($acc:tt)*] $docs:tt $compl:tt - - $name:ident $(| - $short:ident)? $(, $($rest:tt)*)?) => {
+        $crate::command!(@parse $info [$($acc)* ($docs $compl flag [$name] [$($short)?] bool)] [] [] $($($rest)*)?)
+    };
+    // variadic argument with optional bounds
+    (@parse $info:tt [$($acc:tt)*] $docs:tt $compl:tt $($name:ident)? : Vec<$type:ty> [$range:expr] $(, $($rest:tt)*)?) => {
+        $crate::command!(@parse $info [$($acc)* ($docs $compl variadic [$($name)?] [] $type, $range)] [] [] $($($rest)*)?)
+    };
+    (@parse $info:tt [$($acc:tt)*] $docs:tt $compl:tt $($name:ident)? : Vec<$type:ty> $(, $($rest:tt)*)?) => {
+        $crate::command!(@parse $info [$($acc)* ($docs $compl variadic [$($name)?] [] $type, ..)] [] [] $($($rest)*)?)
+    };
+    // optional argument without default
+    (@parse $info:tt [$($acc:tt)*] $docs:tt $compl:tt $($name:ident)? : Option<$type:ty> $(, $($rest:tt)*)?) => {
+        $crate::command!(@parse $info [$($acc)* ($docs $compl optional [$($name)?] [] $type)] [] [] $($($rest)*)?)
+    };
+    // optional argument with a default value
+    (@parse $info:tt [$($acc:tt)*] $docs:tt $compl:tt $($name:ident)? : $type:ty = $default:expr $(, $($rest:tt)*)?) => {
+        $crate::command!(@parse $info [$($acc)* ($docs $compl default [$($name)?] [] $type, $default)] [] [] $($($rest)*)?)
+    };
+    // required argument
+    (@parse $info:tt [$($acc:tt)*] $docs:tt $compl:tt $($name:ident)? : $type:ty $(, $($rest:tt)*)?) => {
+        $crate::command!(@parse $info [$($acc)* ($docs $compl required [$($name)?] [] $type)] [] [] $($($rest)*)?)
+    };
+    // when all arguments have been parsed, generate the command
+    (@parse [$mode:tt, $description:expr, $handler:expr, $ctx:ident, [$($pass_ctx:ident)?], [$($state:ident $stype:ty)?]]
+        [$( ([$($doc:expr),*] [$($compl:expr)?] $kind:ident [$($name:ident)?] [$($short:ident)?] $type:ty $(, $default:expr)?) )*] [] []) => {{
+        // (is_option, info) for each argument
+        let info: &[(bool, String)] = &[ $(
+            $crate::command!(@info $kind [$($name)?] [$($short)?] $type $(, $default)?)
+        ),* ];
+        // lines of doc comments for each argument
+        let docs: &[&[&str]] = &[ $( &[$($doc),*] ),* ];
+        const _: () = $crate::command::check_arg_order(&[ $( $crate::command!(@order $kind) ),* ]);
+        let mut cmd = $crate::command::Command::with_context($description,
+            move |$ctx: &mut $crate::ReplContext, args: &[&str]| -> $crate::anyhow::Result<$crate::command::CommandStatus> {
+                #[allow(unused_variables)]
+                let values = $crate::command::assign_args(&[ $(
+                    $crate::command!(@kind $kind [$($name)?] [$($short)?] $(, $default)?)
+                ),* ], args)?;
+                $( let $state = $ctx.take_state::<$stype>()?; )?
+                #[allow(unused_mut)]
+                let mut handler = $handler;
+                let result = $crate::command!(@call handler, values, 0; [$( ($kind $type $(, $default)?) )*] [$($pass_ctx;)? $($state;)?]);
+                $crate::command!(@finish $mode $ctx result)
+            });
+        cmd.args_info = info.iter().filter(|(opt, _)| !opt).map(|(_, i)| i.clone()).collect();
+        cmd.options_info = info.iter().filter(|(opt, _)| *opt).map(|(_, i)| i.clone()).collect();
+        cmd.arg_descriptions = $crate::command::describe_args(info, docs);
+        cmd.background = $crate::command!(@background $mode);
+        $( cmd.state_type = Some($crate::command::StateType::of::<$stype>()); )?
+        cmd.arg_completers = vec![ $( (
+            $crate::command!(@kind $kind [$($name)?] [$($short)?] $(, $default)?),
+            $crate::command!(@completer $kind [$($compl)?] $type),
+        ) ),* ];
+        cmd
+    }};
+    (@background job) => { true };
+    (@background $mode:tt) => { false };
+    (@finish sync $ctx:ident $result:ident) => { $result };
+    // the job is executed by the REPL after the handler returns, see `job_command!`
+    (@finish job $ctx:ident $result:ident) => {{
+        $ctx.run_job($result);
+        Ok($crate::command::CommandStatus::Done)
+    }};
+    // the future is awaited by `AsyncRepl` after the handler returns
+    (@finish async $ctx:ident $result:ident) => {{
+        $ctx.run_async($result);
+        Ok($crate::command::CommandStatus::Done)
+    }};
+    (@info required [$($name:ident)?] [] $type:ty) => {
+        (false, format!("{}:{}", concat!($(stringify!($name))?), $crate::command!(@type $type)))
+    };
+    (@info default [$($name:ident)?] [] $type:ty, $default:expr) => {
+        (false, format!("[{}:{}={}]", concat!($(stringify!($name))?), $crate::command!(@type $type), stringify!($default)))
+    };
+    (@info optional [$($name:ident)?] [] $type:ty) => {
+        (false, format!("[{}:{}]", concat!($(stringify!($name))?), $crate::command!(@type $type)))
     };
-    // transform element of $args into parsed function argument by calling .parse::<$type>().unwrap()
-    // on each, this starts a recursive muncher that constructs following argument getters args[i]
-    (@handler_call $handler:ident; $args:ident; $($types:ty;)*) => {
-        command!(@handler_call $handler, $args, 0; $($types;)* =>)
+    (@info variadic [$($name:ident)?] [] $type:ty, $range:expr) => {
+        (false, format!("{}:{}...", concat!($(stringify!($name))?), $crate::command!(@type $type)))
     };
-    // $num is used to index $args; pop $type from beginning of list, add new parsed at the endo of $parsed
-    (@handler_call $handler:ident, $args:ident, $num:expr; $type:ty; $($types:ty;)* => $($parsed:expr;)*) => {
-        command!(@handler_call $handler, $args, $num + 1;
-            $($types;)* =>
-            $($parsed;)* $args[$num].parse::<$type>().unwrap();
-        )
+    (@info flag [$name:ident] [$($short:ident)?] $type:ty) => {
+        (true, concat!($("-", stringify!($short), ", ", )? "--", stringify!($name)).to_string())
     };
-    // finally when there are no more types emit code that calls the handler with all arguments parsed
-    (@handler_call $handler:ident, $args:ident, $num:expr; => $($parsed:expr;)*) => {
+    (@info named [$name:ident] [$($short:ident)?] $type:ty) => {
+        (true, format!("{}--{} <{}>", concat!($("-", stringify!($short), ", ")?), stringify!($name),
+            $crate::command!(@type $type)))
+    };
+    (@info named_default [$name:ident] [$($short:ident)?] $type:ty, $default:expr) => {
+        (true, format!("{}--{} <{}={}>", concat!($("-", stringify!($short), ", ")?), stringify!($name),
+            $crate::command!(@type $type), stringify!($default)))
+    };
+    // type name shown in help, or the list of choices for types implementing `Choice`
+    (@type $type:ty) => {{
+        #[allow(unused_imports)]
+        use $crate::command::{ChoiceTypeInfo as _, OtherTypeInfo as _};
+        (&$crate::command::TypeInfo::<$type>::new()).type_info(stringify!($type))
+    }};
+    // kinds of positional arguments with the same order rules, but that can be used in constants
+    (@order required) => { $crate::command::ArgKind::Required };
+    (@order default) => { $crate::command::ArgKind::Optional };
+    (@order optional) => { $crate::command::ArgKind::Optional };
+    (@order variadic) => { $crate::command::ArgKind::Variadic { min: 0, max: None } };
+    (@order $kind:ident) => { $crate::command::ArgKind::Flag { long: "", short: None } };
+    (@kind required $_name:tt []) => { $crate::command::ArgKind::Required };
+    (@kind default $_name:tt [], $default:expr) => { $crate::command::ArgKind::Optional };
+    (@kind optional $_name:tt []) => { $crate::command::ArgKind::Optional };
+    (@kind variadic $_name:tt [], $range:expr) => { $crate::command::ArgKind::variadic($range) };
+    (@kind flag [$name:ident] [$($short:ident)?]) => {
+        $crate::command::ArgKind::Flag { long: stringify!($name), short: $crate::command!(@short $($short)?) }
+    };
+    (@kind named [$name:ident] [$($short:ident)?]) => {
+        $crate::command::ArgKind::Named { long: stringify!($name), short: $crate::command!(@short $($short)?) }
+    };
+    (@kind named_default [$name:ident] [$($short:ident)?], $default:expr) => {
+        $crate::command::ArgKind::Named { long: stringify!($name), short: $crate::command!(@short $($short)?) }
+    };
+    (@completer flag [] $type:ty) => { None };
+    (@completer $kind:ident [$compl:expr] $type:ty) => {
+        Some(::std::sync::Arc::new($compl) as ::std::sync::Arc<dyn $crate::completion::ArgCompleter>)
+    };
+    // use the completer of the type if it implements `Completable`
+    (@completer $kind:ident [] $type:ty) => {{
+        #[allow(unused_imports)]
+        use $crate::completion::{CompletableType as _, OtherType as _};
+        (&$crate::completion::TypeCompleter::<$type>::new()).completer()
+    }};
+    (@short) => { None };
+    (@short $short:ident) => { Some(stringify!($short)) };
+    // transform each argument value into parsed function argument, this is a recursive muncher
+    // where $num is used to index $values; pop entry from the first list, push parsed to the second
+    (@call $handler:ident, $values:ident, $num:expr; [(required $type:ty) $($rest:tt)*] [$($parsed:expr;)*]) => {
+        $crate::command!(@call $handler, $values, $num + 1; [$($rest)*] [$($parsed;)*
+            $crate::command::parse_arg::<$type>($values[$num][0])?;
+        ])
+    };
+    (@call $handler:ident, $values:ident, $num:expr; [(default $type:ty, $default:expr) $($rest:tt)*] [$($parsed:expr;)*]) => {
+        $crate::command!(@call $handler, $values, $num + 1; [$($rest)*] [$($parsed;)*
+            match $values[$num].first() {
+                Some(value) => $crate::command::parse_arg::<$type>(value)?,
+                None => $default,
+            };
+        ])
+    };
+    (@call $handler:ident, $values:ident, $num:expr; [(optional $type:ty) $($rest:tt)*] [$($parsed:expr;)*]) => {
+        $crate::command!(@call $handler, $values, $num + 1; [$($rest)*] [$($parsed;)*
+            $values[$num].first().map(|value| $crate::command::parse_arg::<$type>(value)).transpose()?;
+        ])
+    };
+    (@call $handler:ident, $values:ident, $num:expr; [(named_default $type:ty, $default:expr) $($rest:tt)*] $parsed:tt) => {
+        $crate::command!(@call $handler, $values, $num; [(default $type, $default) $($rest)*] $parsed)
+    };
+    (@call $handler:ident, $values:ident, $num:expr; [(named $type:ty) $($rest:tt)*] $parsed:tt) => {
+        $crate::command!(@call $handler, $values, $num; [(optional $type) $($rest)*] $parsed)
+    };
+    (@call $handler:ident, $values:ident, $num:expr; [(flag $type:ty) $($rest:tt)*] [$($parsed:expr;)*]) => {
+        $crate::command!(@call $handler, $values, $num + 1; [$($rest)*] [$($parsed;)*
+            !$values[$num].is_empty();
+        ])
+    };
+    (@call $handler:ident, $values:ident, $num:expr; [(variadic $type:ty, $range:expr) $($rest:tt)*] [$($parsed:expr;)*]) => {
+        $crate::command!(@call $handler, $values, $num + 1; [$($rest)*] [$($parsed;)*
+            $crate::command::parse_variadic::<$type>(&$values[$num])?;
+        ])
+    };
+    // finally when there are no more entries emit code that calls the handler with all arguments parsed
+    (@call $handler:ident, $values:ident, $num:expr; [] [$($parsed:expr;)*]) => {
         $handler( $($parsed),* )
     };
 }
@@ -246,12 +1198,8 @@ mod tests {
 
     #[test]
     fn manual_command() {
-        let mut cmd = Command {
-            description: "Test command".into(),
-            args_info: vec![],
-            handler: Box::new(|_args| Ok(CommandStatus::Done)),
-        };
-        match (cmd.handler)(&[]) {
+        let mut cmd = Command::new("Test command", |_args| Ok(CommandStatus::Done));
+        match cmd.run(&[]) {
             Ok(CommandStatus::Done) => {}
             _ => panic!("Wrong variant"),
         };
@@ -352,4 +1300,367 @@ mod tests {
         };
         assert_eq!(cmd.args_info, &["number:i32", "name:String", ":f32"]);
     }
+
+    #[test]
+    fn command_optional_args() {
+        let mut cmd = command! {
+            "Example cmd",
+            (x: i32, y: i32 = 5, z: Option<String>) => |x, y, z: Option<String>| {
+                assert_eq!(x, 1);
+                match z.as_deref() {
+                    None => assert_eq!(y, 5),
+                    Some("z") => assert_eq!(y, 2),
+                    other => panic!("Wrong value: {:?}", other),
+                }
+                Ok(CommandStatus::Done)
+            }
+        };
+        assert!(cmd.run(&["1"]).is_ok());
+        assert!(cmd.run(&["1", "5"]).is_ok());
+        assert!(cmd.run(&["1", "2", "z"]).is_ok());
+        assert!(cmd.run(&["1", "x"]).is_err());
+    }
+
+    #[test]
+    fn command_optional_args_count() {
+        let mut cmd = command!("Example cmd", (:i32, :i32 = 0) => |_x, _y| Ok(CommandStatus::Done));
+        let err = cmd.run(&[]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ArgsError>(),
+            Some(ArgsError::TooFewArguments { got: 0, min: 1 })
+        ));
+        let err = cmd.run(&["1", "2", "3"]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ArgsError>(),
+            Some(ArgsError::TooManyArguments { got: 3, max: 2 })
+        ));
+    }
+
+    #[test]
+    fn command_optional_args_info() {
+        let cmd = command! {
+            "Example cmd",
+            (x: i32, y: u8 = 3, :Option<String>) => |_x, _y, _z| Ok(CommandStatus::Done)
+        };
+        assert_eq!(cmd.args_info, &["x:i32", "[y:u8=3]", "[:String]"]);
+        assert_eq!(cmd.arg_types(), &["i32", "u8", "String"]);
+    }
+
+    #[test]
+    fn command_variadic_args() {
+        let mut cmd = command! {
+            "Example cmd",
+            (x: i32, rest: Vec<u8>) => |x, rest: Vec<u8>| {
+                assert_eq!(x as usize, rest.len());
+                Ok(CommandStatus::Done)
+            }
+        };
+        assert_eq!(cmd.args_info, &["x:i32", "rest:u8..."]);
+        assert!(cmd.run(&["0"]).is_ok());
+        assert!(cmd.run(&["3", "1", "2", "3"]).is_ok());
+        let err = cmd.run(&["2", "1", "x"]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ArgsError>(),
+            Some(ArgsError::WrongArgumentValue { index: Some(1), .. })
+        ));
+    }
+
+    #[test]
+    fn command_variadic_args_bounds() {
+        let mut cmd = command!("Example cmd", (:Vec<u8> [1..3]) => |_v| Ok(CommandStatus::Done));
+        let err = cmd.run(&[]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ArgsError>(),
+            Some(ArgsError::TooFewArguments { got: 0, min: 1 })
+        ));
+        assert!(cmd.run(&["1", "2"]).is_ok());
+        let err = cmd.run(&["1", "2", "3"]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ArgsError>(),
+            Some(ArgsError::TooManyArguments { got: 3, max: 2 })
+        ));
+    }
+
+    #[test]
+    fn command_arg_completers() {
+        let cmd = command! {
+            "Example cmd",
+            (
+                path: std::path::PathBuf,
+                #[complete(|prefix: &str| vec![format!("{prefix}1")])]
+                n: u32,
+                /// Enable
+                enable: Option<bool>,
+                --verbose,
+                #[complete(vec!["a".to_string(), "b".to_string()])]
+                --mode: String,
+                values: Vec<i32>,
+            ) => |_path, _n, _enable, _verbose, _mode, _values| Ok(CommandStatus::Done)
+        };
+        let kinds: Vec<_> = cmd.arg_completers.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(
+            kinds[..3],
+            [ArgKind::Required, ArgKind::Required, ArgKind::Optional]
+        );
+        assert!(matches!(kinds[4], ArgKind::Named { long: "mode", .. }));
+        let complete = |i: usize, prefix| {
+            let completer = cmd.arg_completers[i].1.as_ref();
+            completer.map(|c| c.complete(prefix))
+        };
+        assert_eq!(complete(1, "4"), Some(vec!["41".into()]));
+        assert_eq!(complete(2, ""), Some(vec!["true".into(), "false".into()]));
+        assert_eq!(complete(3, ""), None);
+        assert_eq!(complete(4, "b"), Some(vec!["b".into()]));
+        assert_eq!(complete(5, ""), None);
+        assert_eq!(
+            cmd.arg_descriptions,
+            &[("[enable:bool]".into(), "Enable".into())]
+        );
+    }
+
+    #[test]
+    fn assign_args_with_optional_and_variadic() {
+        let kinds = [ArgKind::Required, ArgKind::Optional, ArgKind::variadic(..)];
+        let values = assign_args(&kinds, &["a"]).unwrap();
+        assert_eq!(values, vec![vec!["a"], vec![], vec![]]);
+        let values = assign_args(&kinds, &["a", "b", "c", "d"]).unwrap();
+        assert_eq!(values, vec![vec!["a"], vec!["b"], vec!["c", "d"]]);
+    }
+
+    #[test]
+    fn assign_args_optional_before_required() {
+        let kinds = [ArgKind::Optional, ArgKind::Required];
+        let values = assign_args(&kinds, &["5"]).unwrap();
+        assert_eq!(values, vec![vec![], vec!["5"]]);
+        let values = assign_args(&kinds, &["4", "5"]).unwrap();
+        assert_eq!(values, vec![vec!["4"], vec!["5"]]);
+    }
+
+    #[test]
+    fn assign_args_variadic_before_required() {
+        let kinds = [ArgKind::variadic(..), ArgKind::Required];
+        let values = assign_args(&kinds, &["1", "2"]).unwrap();
+        assert_eq!(values, vec![vec!["1"], vec!["2"]]);
+        let kinds = [
+            ArgKind::variadic(..=1),
+            ArgKind::Optional,
+            ArgKind::Required,
+        ];
+        let values = assign_args(&kinds, &["1", "2", "3"]).unwrap();
+        assert_eq!(values, vec![vec!["1"], vec!["2"], vec!["3"]]);
+    }
+
+    #[test]
+    fn assign_args_with_options() {
+        let kinds = [
+            ArgKind::Required,
+            ArgKind::Flag {
+                long: "verbose",
+                short: Some("v"),
+            },
+            ArgKind::Named {
+                long: "timeout",
+                short: Some("t"),
+            },
+        ];
+        let values = assign_args(&kinds, &["-v", "a", "--timeout", "5"]).unwrap();
+        assert_eq!(values, vec![vec!["a"], vec!["-v"], vec!["5"]]);
+        let values = assign_args(&kinds, &["--timeout=-1", "-5"]).unwrap();
+        assert_eq!(values, vec![vec!["-5"], vec![], vec!["-1"]]);
+        let values = assign_args(&kinds, &["--", "--verbose"]).unwrap();
+        assert_eq!(values, vec![vec!["--verbose"], vec![], vec![]]);
+    }
+
+    #[test]
+    fn assign_args_invalid_options() {
+        let kinds = [
+            ArgKind::Flag {
+                long: "verbose",
+                short: None,
+            },
+            ArgKind::Named {
+                long: "timeout",
+                short: Some("t"),
+            },
+        ];
+        let check = |args: &[&str], expected: &str| match assign_args(&kinds, args) {
+            Err(err) => assert_eq!(err.to_string(), expected),
+            Ok(v) => panic!("Unexpected success: {:?}", v),
+        };
+        check(&["--foo"], "unknown option '--foo'");
+        check(&["-t"], "missing value for option '-t'");
+        check(&["--verbose=1"], "option '--verbose' does not take a value");
+        check(&["x"], "wrong number of arguments: got 1, expected 0");
+    }
+
+    #[test]
+    fn command_flags_and_options() {
+        let mut cmd = command! {
+            "Example cmd",
+            (--verbose|-v, x: i32, --scale: i32 = 2, --offset|-o: i32) => |verbose, x, scale, offset: Option<i32>| {
+                assert_eq!(x * scale + offset.unwrap_or(0), if verbose { 10 } else { 0 });
+                Ok(CommandStatus::Done)
+            }
+        };
+        assert!(cmd.run(&["0"]).is_ok());
+        assert!(cmd.run(&["5", "-v"]).is_ok());
+        assert!(cmd
+            .run(&["--scale", "3", "4", "-o", "-2", "--verbose"])
+            .is_ok());
+        assert!(cmd.run(&["--scale", "x", "4"]).is_err());
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derive_args() {
+        #[derive(crate::ReplArgs, Debug, PartialEq)]
+        struct Args {
+            /// Input file
+            input: std::path::PathBuf,
+            #[repl(default = 1)]
+            count: u32,
+            /// Names
+            ///   of things
+            names: Vec<String>,
+            /// Verbose output
+            #[repl(flag, short = 'v')]
+            verbose: bool,
+            #[repl(option, complete = ["1", "2", "3"])]
+            level: Option<u8>,
+            #[repl(option, short = 's', default = 1.0)]
+            scale: f32,
+        }
+        assert_eq!(
+            Args::args_info(),
+            &[
+                "input:std::path::PathBuf",
+                "[count:u32=1]",
+                "names:String..."
+            ]
+        );
+        assert_eq!(
+            Args::options_info(),
+            &["-v, --verbose", "--level <u8>", "-s, --scale <f32=1.0>"]
+        );
+        assert_eq!(
+            Args::arg_descriptions(),
+            &[
+                ("input:std::path::PathBuf".into(), "Input file".into()),
+                ("names:String...".into(), "Names of things".into()),
+                ("-v, --verbose".into(), "Verbose output".into()),
+            ]
+        );
+        let completers = Args::arg_completers();
+        let has_completer: Vec<_> = completers.iter().map(|(_, c)| c.is_some()).collect();
+        assert_eq!(has_completer, [true, false, false, false, true, false]);
+        assert_eq!(completers[4].1.as_ref().unwrap().complete("2"), &["2"]);
+        let args = Args::parse(&["in.txt", "-v", "3", "a", "--level", "2", "b"]).unwrap();
+        let expected = Args {
+            input: "in.txt".into(),
+            count: 3,
+            names: vec!["a".into(), "b".into()],
+            verbose: true,
+            level: Some(2),
+            scale: 1.0,
+        };
+        assert_eq!(args, expected);
+        assert!(matches!(
+            Args::parse(&["in.txt", "x"]),
+            Err(ArgsError::WrongArgumentValue { .. })
+        ));
+        assert!(matches!(
+            Args::parse(&[]),
+            Err(ArgsError::TooFewArguments { .. })
+        ));
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn command_from_args() {
+        #[derive(crate::ReplArgs)]
+        struct Args {
+            x: i32,
+            #[repl(flag)]
+            negate: bool,
+        }
+        let mut cmd = Command::from_args("Example cmd", |args: Args| {
+            assert_eq!(if args.negate { -args.x } else { args.x }, 3);
+            Ok(CommandStatus::Done)
+        });
+        assert!(cmd.run(&["3"]).is_ok());
+        assert!(cmd.run(&["-3", "--negate"]).is_ok());
+        assert!(cmd.run(&[]).is_err());
+    }
+
+    #[cfg(feature = "derive")]
+    #[derive(crate::Choice, Debug, PartialEq)]
+    enum Mode {
+        Fast,
+        Safe,
+        Slow,
+        DryRun,
+        #[repl(name = "dbg")]
+        Debug,
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn choice_parse() {
+        assert_eq!(Mode::choices(), &["fast", "safe", "slow", "dry-run", "dbg"]);
+        assert_eq!("slow".parse::<Mode>().unwrap(), Mode::Slow);
+        assert_eq!("sa".parse::<Mode>().unwrap(), Mode::Safe);
+        assert_eq!(
+            "d".parse::<Mode>().unwrap_err().to_string(),
+            "ambiguous value, matches: dry-run, dbg"
+        );
+        assert_eq!(
+            "".parse::<Mode>().unwrap_err().to_string(),
+            "expected one of: fast, safe, slow, dry-run, dbg"
+        );
+        assert_eq!(Mode::DryRun.to_string(), "dry-run");
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn command_choice_args() {
+        let mut cmd = command! {
+            "Example cmd",
+            (mode: Mode, rest: Vec<Mode>, --level: Mode = Mode::Fast) => |mode, rest: Vec<Mode>, level| {
+                assert_eq!((mode, rest.len(), level), (Mode::Debug, 1, Mode::Slow));
+                Ok(CommandStatus::Done)
+            }
+        };
+        assert_eq!(
+            cmd.args_info,
+            &[
+                "mode:{fast|safe|slow|dry-run|dbg}",
+                "rest:{fast|safe|slow|dry-run|dbg}..."
+            ]
+        );
+        assert_eq!(
+            cmd.options_info,
+            &["--level <{fast|safe|slow|dry-run|dbg}=Mode::Fast>"]
+        );
+        assert!(cmd.run(&["dbg", "dr", "--level", "sl"]).is_ok());
+        let err = cmd.run(&["dbg", "x"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("(element 0): expected one of: fast, safe, slow, dry-run, dbg"));
+        let completer = cmd.arg_completers[0].1.as_ref().unwrap();
+        assert_eq!(completer.complete("s"), &["safe", "slow"]);
+
+        #[derive(crate::ReplArgs)]
+        struct Args {
+            /// Mode
+            #[repl(option, short = 'm')]
+            mode: Option<Mode>,
+        }
+        assert_eq!(
+            Args::options_info(),
+            &["-m, --mode <{fast|safe|slow|dry-run|dbg}>"]
+        );
+        assert_eq!(Args::arg_descriptions()[0].0, Args::options_info()[0]);
+        assert!(Args::arg_completers()[0].1.is_some());
+        assert_eq!(Args::parse(&["-m", "sa"]).unwrap().mode, Some(Mode::Safe));
+    }
 }
@@ -1,17 +1,485 @@
-use std::rc::Rc;
+//! Completion of command names and argument values.
+//!
+//! Values of command arguments are completed using [`ArgCompleter`]s, which can be given
+//! for each argument in [`command!`](crate::command!), e.g. `#[complete(["fast", "safe"])]`.
+//! If not given, the completer is chosen based on the argument type, for types that
+//! implement [`Completable`], e.g. [`bool`], [`PathBuf`] and enums deriving [`Choice`](crate::Choice).
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use rustyline::{
     completion::{Completer, FilenameCompleter, Pair},
     hint::Hinter,
 };
 use rustyline_derive::{Helper, Highlighter, Validator};
-use trie_rs::Trie;
+use trie_rs::{Trie, TrieBuilder};
 
+use crate::command::ArgKind;
 use crate::repl::split_args;
 
+/// Names of commands and groups on all levels of the command tree.
+///
+/// Commands and groups are identified by paths - their names prefixed with names
+/// of all the parent groups, separated with spaces, e.g. `"net show"`. The top level
+/// group has an empty path.
+pub(crate) struct NameTree {
+    /// Sorted names of commands and groups directly inside each group
+    levels: HashMap<String, (Vec<String>, Trie<u8>)>,
+    groups: HashSet<String>,
+}
+
+/// Result of resolving command path from input words.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Resolved {
+    /// Path to a command and the number of words that have been used
+    Command(String, usize),
+    /// All words resolved to a path to a group
+    Group(String),
+    /// Could not resolve, path that has been entered and matching candidates
+    NotFound(String, Vec<String>),
+}
+
+pub(crate) fn join_path(group: &str, name: &str) -> String {
+    if group.is_empty() {
+        name.to_string()
+    } else {
+        format!("{group} {name}")
+    }
+}
+
+impl NameTree {
+    pub(crate) fn new<'n>(
+        commands: impl IntoIterator<Item = &'n str>,
+        groups: impl IntoIterator<Item = &'n str>,
+    ) -> Self {
+        let groups: HashSet<String> = groups.into_iter().map(String::from).collect();
+        let mut names: HashMap<String, Vec<String>> = HashMap::new();
+        names.entry(String::new()).or_default();
+        for group in groups.iter() {
+            names.entry(group.clone()).or_default();
+        }
+        let paths = commands.into_iter().map(String::from);
+        for path in paths.chain(groups.iter().cloned()) {
+            let (group, name) = path.rsplit_once(' ').unwrap_or(("", &path));
+            names
+                .entry(group.to_string())
+                .or_default()
+                .push(name.to_string());
+        }
+        let levels = names
+            .into_iter()
+            .map(|(group, mut names)| {
+                // trie-rs requires pushing words that are prefixes of other words first
+                names.sort_unstable();
+                names.dedup();
+                let mut trie = TrieBuilder::new();
+                for name in names.iter() {
+                    trie.push(name);
+                }
+                (group, (names, trie.build()))
+            })
+            .collect();
+        NameTree { levels, groups }
+    }
+
+    pub(crate) fn is_group(&self, path: &str) -> bool {
+        self.groups.contains(path)
+    }
+
+    /// Names inside `group` starting with given prefix
+    pub(crate) fn candidates(&self, group: &str, prefix: &str) -> Vec<String> {
+        match self.levels.get(group) {
+            // trie does not support empty queries
+            Some((names, _)) if prefix.is_empty() => names.clone(),
+            Some((_, trie)) => trie
+                .predictive_search(prefix)
+                .into_iter()
+                .map(|bytes| String::from_utf8(bytes).unwrap())
+                .collect(),
+            None => Vec::with_capacity(0),
+        }
+    }
+
+    /// Find name inside `group`, if `predict` is true then prefix of a name is enough
+    /// as long as there is only one name matching it
+    pub(crate) fn find(
+        &self,
+        group: &str,
+        prefix: &str,
+        predict: bool,
+    ) -> Result<String, Vec<String>> {
+        let candidates = self.candidates(group, prefix);
+        if candidates.iter().any(|c| c == prefix) {
+            Ok(prefix.to_string())
+        } else if predict && candidates.len() == 1 {
+            Ok(candidates.into_iter().next().unwrap())
+        } else {
+            Err(candidates)
+        }
+    }
+
+    /// Names inside `group` with the smallest edit distance to `name`, if not greater than `max_distance`
+    pub(crate) fn similar(&self, group: &str, name: &str, max_distance: usize) -> Vec<&str> {
+        let names = match self.levels.get(group) {
+            Some((names, _)) => names,
+            None => return vec![],
+        };
+        let distances = names
+            .iter()
+            .map(|n| (strsim::damerau_levenshtein(name, n), n.as_str()));
+        let mut similar: Vec<_> = distances.filter(|(d, _)| *d <= max_distance).collect();
+        let min = similar.iter().map(|(d, _)| *d).min();
+        similar.retain(|(d, _)| Some(*d) == min);
+        similar.into_iter().map(|(_, n)| n).collect()
+    }
+
+    /// Resolve path to a command or group, by finding subsequent words on each level
+    pub(crate) fn resolve<S: AsRef<str>>(&self, words: &[S], predict: bool) -> Resolved {
+        let mut group = String::new();
+        for (i, word) in words.iter().enumerate() {
+            match self.find(&group, word.as_ref(), predict) {
+                Ok(name) => {
+                    let path = join_path(&group, &name);
+                    if !self.is_group(&path) {
+                        return Resolved::Command(path, i + 1);
+                    }
+                    group = path;
+                }
+                Err(candidates) => {
+                    let candidates = candidates.iter().map(|c| join_path(&group, c)).collect();
+                    return Resolved::NotFound(join_path(&group, word.as_ref()), candidates);
+                }
+            }
+        }
+        Resolved::Group(group)
+    }
+}
+
+/// Completion of argument values.
+///
+/// Implemented for closures `Fn(&str) -> Vec<String>` and for lists of possible values.
+/// Completers have to be `Send + Sync`, also without the `async` feature, as `AsyncRepl`
+/// reads lines in a separate thread and features must not change the required bounds.
+pub trait ArgCompleter: Send + Sync {
+    /// Return values that start with `prefix`.
+    fn complete(&self, prefix: &str) -> Vec<String>;
+}
+
+impl<F> ArgCompleter for F
+where
+    F: Fn(&str) -> Vec<String> + Send + Sync,
+{
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        self(prefix)
+    }
+}
+
+impl<const N: usize> ArgCompleter for [&'static str; N] {
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        complete_values(self.iter(), prefix)
+    }
+}
+
+impl ArgCompleter for &'static [&'static str] {
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        complete_values(self.iter(), prefix)
+    }
+}
+
+impl ArgCompleter for Vec<String> {
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        complete_values(self.iter(), prefix)
+    }
+}
+
+fn complete_values<S: AsRef<str>>(values: impl Iterator<Item = S>, prefix: &str) -> Vec<String> {
+    values
+        .filter(|v| v.as_ref().starts_with(prefix))
+        .map(|v| v.as_ref().to_string())
+        .collect()
+}
+
+/// Completion of file and directory names, directories end with `/`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathCompleter;
+
+impl ArgCompleter for PathCompleter {
+    fn complete(&self, prefix: &str) -> Vec<String> {
+        let (dir, name) = match prefix.rfind('/') {
+            Some(i) => (&prefix[..=i], &prefix[i + 1..]),
+            None => ("", prefix),
+        };
+        let entries = match std::fs::read_dir(if dir.is_empty() { "." } else { dir }) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        let mut candidates: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().into_string().ok()?;
+                // hidden files only when explicitly requested
+                if !file_name.starts_with(name) || (file_name.starts_with('.') && name.is_empty()) {
+                    return None;
+                }
+                let is_dir = entry.path().is_dir();
+                Some(format!("{dir}{file_name}{}", if is_dir { "/" } else { "" }))
+            })
+            .collect();
+        candidates.sort();
+        candidates
+    }
+}
+
+/// Types that provide completion of their values, used for arguments of this type
+/// when no completer has been specified.
+pub trait Completable {
+    /// Completer for values of this type.
+    fn completer() -> Arc<dyn ArgCompleter>;
+}
+
+impl Completable for bool {
+    fn completer() -> Arc<dyn ArgCompleter> {
+        Arc::new(["true", "false"])
+    }
+}
+
+impl Completable for PathBuf {
+    fn completer() -> Arc<dyn ArgCompleter> {
+        Arc::new(PathCompleter)
+    }
+}
+
+/// Selects completer based on argument type, used by [`command!`](crate::command!).
+///
+/// Uses autoref-based specialization: `(&TypeCompleter::<T>::new()).completer()` returns
+/// the completer of `T` if it implements [`Completable`] and [`None`] otherwise.
+#[doc(hidden)]
+pub struct TypeCompleter<T>(std::marker::PhantomData<T>);
+
+impl<T> TypeCompleter<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        TypeCompleter(std::marker::PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait CompletableType {
+    fn completer(&self) -> Option<Arc<dyn ArgCompleter>>;
+}
+
+impl<T: Completable> CompletableType for TypeCompleter<T> {
+    fn completer(&self) -> Option<Arc<dyn ArgCompleter>> {
+        Some(T::completer())
+    }
+}
+
+#[doc(hidden)]
+pub trait OtherType {
+    fn completer(&self) -> Option<Arc<dyn ArgCompleter>>;
+}
+
+impl<T> OtherType for &TypeCompleter<T> {
+    fn completer(&self) -> Option<Arc<dyn ArgCompleter>> {
+        None
+    }
+}
+
+/// Kinds of command arguments with their completers, see [`Command::arg_completers`](crate::Command::arg_completers).
+pub type ArgCompleters = Vec<(ArgKind, Option<Arc<dyn ArgCompleter>>)>;
+
+/// Completion of command and group names, variables and argument values, passed to
+/// [`LineSource`](crate::source::LineSource)s.
+#[derive(Clone)]
+pub struct CommandCompleter {
+    pub(crate) names: Arc<NameTree>,
+    /// Sorted names of variables
+    pub(crate) variables: Arc<Vec<String>>,
+    /// Argument completers of all overloads of each command
+    pub(crate) args: Arc<HashMap<String, Vec<ArgCompleters>>>,
+    pub(crate) predict_commands: bool,
+}
+
+impl CommandCompleter {
+    /// If the cursor at `pos` is on a command or group name, a variable name after `$`,
+    /// or an argument that has a completer, return the start of the word in `line` and the
+    /// values that it can be completed to.
+    ///
+    /// No candidates are returned on an empty line.
+    pub fn complete(&self, line: &str, pos: usize) -> Option<(usize, Vec<String>)> {
+        self.complete_variable(line, pos)
+            .or_else(|| self.complete_command(line, pos))
+            .or_else(|| self.complete_arg(line, pos))
+    }
+
+    /// Complete value of the argument that the cursor is on
+    fn complete_arg(&self, line: &str, pos: usize) -> Option<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let mut args = split_args(line).ok()?;
+        let on_new_word = line.ends_with(char::is_whitespace) || args.is_empty();
+        let (start, prefix) = if on_new_word {
+            (line.len(), String::new())
+        } else {
+            let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+            (start, args.pop().unwrap())
+        };
+        let (path, n_words) = match self.names.resolve(&args, self.predict_commands) {
+            Resolved::Command(path, n_words) => (path, n_words),
+            _ => return None,
+        };
+        let overloads = self.args.get(&path)?;
+        let mut candidates: Vec<_> = overloads
+            .iter()
+            .flat_map(|completers| complete_arg_value(completers, &args[n_words..], &prefix))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        candidates.sort();
+        candidates.dedup();
+        Some((start, candidates))
+    }
+
+    /// Complete `$NAME` or `${NAME}`, closing brace is added to candidates. Returns [`None`]
+    /// if no variable matches, e.g. when variables are disabled, so that other completers are used.
+    fn complete_variable(&self, line: &str, pos: usize) -> Option<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let start = line.trim_end_matches(is_name_char).len();
+        let (start, braced) = if line[..start].ends_with("${") {
+            (start, true)
+        } else if line[..start].ends_with('$') {
+            (start, false)
+        } else {
+            return None;
+        };
+        let dollar = line[..start].rfind('$').unwrap();
+        if line[..dollar].ends_with('\\') {
+            return None;
+        }
+        let prefix = &line[start..];
+        let candidates = self
+            .variables
+            .iter()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| {
+                if braced {
+                    format!("{name}}}")
+                } else {
+                    name.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        (!candidates.is_empty()).then_some((start, candidates))
+    }
+
+    fn complete_command(&self, line: &str, pos: usize) -> Option<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        // fails if there is an unmatched quote, so assume there are no arguments at all
+        let mut args = split_args(line).unwrap_or_else(|_e| Vec::with_capacity(0));
+        let on_new_word = line.ends_with(char::is_whitespace) || args.is_empty();
+        let (start, prefix) = if on_new_word {
+            (line.len(), String::new())
+        } else {
+            let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+            (start, args.pop().unwrap())
+        };
+        // all previous words must be groups
+        let group = match self.names.resolve(&args, self.predict_commands) {
+            Resolved::Group(group) => group,
+            _ => return None,
+        };
+        // do not list all the commands on an empty line
+        let candidates = if prefix.is_empty() && group.is_empty() {
+            Vec::with_capacity(0)
+        } else {
+            self.names.candidates(&group, &prefix)
+        };
+        Some((start, candidates))
+    }
+}
+
+/// Find the argument that `prefix` is a value of, given the preceding arguments, and complete it.
+///
+/// Follows the same rules as [`assign_args`](crate::command::assign_args). Option names are
+/// completed if the prefix starts with `-`.
+fn complete_arg_value(completers: &ArgCompleters, words: &[String], prefix: &str) -> Vec<String> {
+    // values are quoted so that they remain a single argument
+    let complete = |i: usize, prefix: &str| match &completers[i].1 {
+        Some(completer) => complete_values(completer.complete(prefix).into_iter(), prefix)
+            .into_iter()
+            .map(|value| shell_words::quote(&value).into_owned())
+            .collect(),
+        None => vec![],
+    };
+    let named = |i: &usize| matches!(completers[*i].0, ArgKind::Named { .. });
+
+    let mut positional = 0;
+    let mut options_end = false;
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        let option = if options_end {
+            None
+        } else if word == "--" {
+            options_end = true;
+            continue;
+        } else if let Some(long) = word.strip_prefix("--") {
+            if long.contains('=') {
+                continue;
+            }
+            completers.iter().position(|(k, _)| k.matches_long(long))
+        } else if let Some(short) = word.strip_prefix('-') {
+            completers.iter().position(|(k, _)| k.matches_short(short))
+        } else {
+            None
+        };
+        match option {
+            // option value may be the word being completed
+            Some(i) if named(&i) => {
+                if words.next().is_none() {
+                    return complete(i, prefix);
+                }
+            }
+            Some(_) => {}
+            None => positional += 1,
+        }
+    }
+
+    if !options_end && prefix.starts_with('-') {
+        if let Some((long, value)) = prefix.strip_prefix("--").and_then(|p| p.split_once('=')) {
+            return match completers.iter().position(|(k, _)| k.matches_long(long)) {
+                Some(i) if named(&i) => complete(i, value)
+                    .into_iter()
+                    .map(|v| format!("--{long}={v}"))
+                    .collect(),
+                _ => vec![],
+            };
+        }
+        let names = completers.iter().filter_map(|(kind, _)| match kind {
+            ArgKind::Flag { long, .. } | ArgKind::Named { long, .. } => Some(format!("--{long}")),
+            _ => None,
+        });
+        return complete_values(names, prefix);
+    }
+
+    let positional_args = completers.iter().enumerate();
+    for (i, (kind, _)) in positional_args.filter(|(_, (k, _))| k.is_positional()) {
+        match kind {
+            ArgKind::Variadic { .. } => return complete(i, prefix),
+            _ if positional == 0 => return complete(i, prefix),
+            _ => positional -= 1,
+        }
+    }
+    vec![]
+}
+
 #[derive(Helper, Validator, Highlighter)]
 pub(crate) struct Completion {
-    pub(crate) trie: Rc<Trie<u8>>,
+    pub(crate) completer: CommandCompleter,
     pub(crate) with_hints: bool,
     pub(crate) with_completion: bool,
     pub(crate) filename_completer: Option<FilenameCompleter>,
@@ -21,20 +489,14 @@ impl Hinter for Completion {
     type Hint = String;
 
     fn hint(&self, line: &str, pos: usize, _ctx: &rustyline::Context<'_>) -> Option<Self::Hint> {
-        if !self.with_hints {
+        if !self.with_hints || pos < line.len() {
             return None;
         }
-        let start = whitespace_before(line);
+        let (start, candidates) = self.completer.complete(line, pos)?;
         let prefix = &line[start..pos];
-        if pos < line.len() || prefix.is_empty() {
-            None
-        } else {
-            let candidates = completion_candidates(&self.trie, prefix);
-            if candidates.len() == 1 {
-                Some(candidates[0][(pos - start)..].into())
-            } else {
-                None
-            }
+        match candidates.as_slice() {
+            [candidate] if !prefix.is_empty() => candidate.strip_prefix(prefix).map(Into::into),
+            _ => None,
         }
     }
 }
@@ -65,39 +527,199 @@ impl Completion {
     fn complete_command(
         &self,
         line: &str,
-        _pos: usize,
+        pos: usize,
         _ctx: &rustyline::Context<'_>,
     ) -> rustyline::Result<Option<(usize, Vec<<Self as Completer>::Candidate>)>> {
-        // fails if there is an unmatched quote, so assume there are no arguments at all
-        let args = split_args(line).unwrap_or_else(|_e| Vec::with_capacity(0));
-        let on_first = args.len() == 1;
-        let completions = if on_first {
-            let candidates = completion_candidates(&self.trie, &args[0])
-                .into_iter()
-                .map(|c| Pair {
-                    display: c.clone(),
-                    replacement: c,
-                })
-                .collect();
-            Some((whitespace_before(line), candidates))
-        } else {
-            None
-        };
+        let completions = self
+            .completer
+            .complete(line, pos)
+            .map(|(start, candidates)| {
+                let candidates = candidates
+                    .into_iter()
+                    .map(|c| Pair {
+                        display: c.clone(),
+                        replacement: c,
+                    })
+                    .collect();
+                (start, candidates)
+            });
         Ok(completions)
     }
 }
 
-pub(crate) fn completion_candidates(trie: &Trie<u8>, prefix: &str) -> Vec<String> {
-    if prefix.is_empty() {
-        Vec::with_capacity(0)
-    } else {
-        trie.predictive_search(prefix)
-            .into_iter()
-            .map(|bytes| String::from_utf8(bytes).unwrap())
-            .collect()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> NameTree {
+        let commands = [
+            "help",
+            "helpme",
+            "net show",
+            "net route add",
+            "net route del",
+        ];
+        NameTree::new(commands, ["net", "net route"])
+    }
+
+    #[test]
+    fn name_tree_candidates() {
+        let names = names();
+        assert_eq!(names.candidates("", "he"), vec!["help", "helpme"]);
+        assert_eq!(names.candidates("net", ""), vec!["route", "show"]);
+        assert_eq!(names.candidates("net route", "d"), vec!["del"]);
+        assert!(names.candidates("help", "").is_empty());
+    }
+
+    #[test]
+    fn complete_variables() {
+        let completer = CommandCompleter {
+            names: Arc::new(names()),
+            variables: Arc::new(vec!["addr".into(), "id".into(), "id2".into()]),
+            args: Default::default(),
+            predict_commands: true,
+        };
+        let complete = |line: &str| completer.complete(line, line.len());
+        assert_eq!(
+            complete("net show $i"),
+            Some((10, vec!["id".into(), "id2".into()]))
+        );
+        assert_eq!(complete("x${a"), Some((3, vec!["addr}".into()])));
+        assert_eq!(
+            complete("x $"),
+            Some((3, vec!["addr".into(), "id".into(), "id2".into()]))
+        );
+        assert_eq!(complete("x \\$a"), None);
+        assert_eq!(complete("x $z"), None);
+        assert_eq!(
+            complete("he"),
+            Some((0, vec!["help".into(), "helpme".into()]))
+        );
     }
-}
 
-fn whitespace_before(line: &str) -> usize {
-    line.chars().take_while(|c| char::is_whitespace(*c)).count()
+    #[test]
+    fn complete_variables_disabled() {
+        let prices: Arc<dyn ArgCompleter> = Arc::new(["$price", "$tax"]);
+        let completer = CommandCompleter {
+            names: Arc::new(names()),
+            variables: Default::default(),
+            args: Arc::new(HashMap::from([(
+                "help".to_string(),
+                vec![vec![(ArgKind::Optional, Some(prices))]],
+            )])),
+            predict_commands: true,
+        };
+        let complete = |line: &str| completer.complete(line, line.len());
+        assert_eq!(complete("help $p"), Some((5, vec!["'$price'".into()])));
+    }
+
+    #[test]
+    fn complete_arguments() {
+        let modes: Arc<dyn ArgCompleter> = Arc::new(["fast", "safe", "slow"]);
+        let hosts: Arc<dyn ArgCompleter> = Arc::new(vec!["local host".to_string()]);
+        let route_add = vec![
+            (ArgKind::Required, Some(hosts)),
+            (
+                ArgKind::Flag {
+                    long: "verbose",
+                    short: Some("v"),
+                },
+                None,
+            ),
+            (
+                ArgKind::Named {
+                    long: "mode",
+                    short: Some("m"),
+                },
+                Some(modes.clone()),
+            ),
+            (ArgKind::variadic(..), Some(bool::completer())),
+        ];
+        let completer = CommandCompleter {
+            names: Arc::new(names()),
+            variables: Default::default(),
+            args: Arc::new(HashMap::from([
+                ("net route add".to_string(), vec![route_add]),
+                (
+                    "help".to_string(),
+                    vec![vec![(ArgKind::Optional, Some(modes))]],
+                ),
+            ])),
+            predict_commands: true,
+        };
+        let complete = |line: &str| {
+            let (start, candidates) = completer.complete(line, line.len())?;
+            Some((start, candidates.join(" ")))
+        };
+        assert_eq!(
+            complete("net route add "),
+            Some((14, "'local host'".into()))
+        );
+        assert_eq!(complete("n r a x --m"), Some((8, "--mode".into())));
+        assert_eq!(complete("n r a x -"), Some((8, "--mode --verbose".into())));
+        assert_eq!(complete("n r a x --mode s"), Some((15, "safe slow".into())));
+        assert_eq!(complete("n r a -m f"), Some((9, "fast".into())));
+        assert_eq!(complete("n r a --mode=f"), Some((6, "--mode=fast".into())));
+        assert_eq!(complete("n r a -v x t"), Some((11, "true".into())));
+        assert_eq!(complete("n r a x -- -v f"), Some((14, "false".into())));
+        assert_eq!(complete("help s"), Some((5, "safe slow".into())));
+        assert_eq!(complete("help fast "), None);
+        assert_eq!(complete("net show "), None);
+    }
+
+    #[test]
+    fn complete_paths() {
+        let dir = std::env::temp_dir().join(format!("easy-repl-paths-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("file.txt"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+        let prefix = format!("{}/", dir.display());
+        let complete = |p: &str| -> Vec<String> {
+            let candidates = PathCompleter.complete(&format!("{prefix}{p}"));
+            candidates
+                .iter()
+                .map(|c| c[prefix.len()..].to_string())
+                .collect()
+        };
+        assert_eq!(complete(""), ["file.txt", "sub/"]);
+        assert_eq!(complete("s"), ["sub/"]);
+        assert_eq!(complete("."), [".hidden"]);
+        assert!(complete("x").is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn name_tree_similar() {
+        let names = names();
+        assert_eq!(names.similar("", "hlep", 2), ["help"]);
+        assert_eq!(names.similar("", "hepme", 2), ["helpme"]);
+        assert_eq!(names.similar("", "xet", 1), ["net"]);
+        assert_eq!(names.similar("net route", "d", 2), ["add", "del"]);
+        assert!(names.similar("", "hlep", 0).is_empty());
+        assert!(names.similar("", "foo", 2).is_empty());
+    }
+
+    #[test]
+    fn name_tree_resolve() {
+        let names = names();
+        let resolve = |line: &str, predict| names.resolve(&split_args(line).unwrap(), predict);
+        assert_eq!(resolve("help x", true), Resolved::Command("help".into(), 1));
+        assert_eq!(
+            resolve("helpm", true),
+            Resolved::Command("helpme".into(), 1)
+        );
+        assert_eq!(
+            resolve("n r a x", true),
+            Resolved::Command("net route add".into(), 3)
+        );
+        assert_eq!(resolve("n r", true), Resolved::Group("net route".into()));
+        assert_eq!(
+            resolve("n r a", false),
+            Resolved::NotFound("n".into(), vec!["net".into()])
+        );
+        assert_eq!(
+            resolve("net x", true),
+            Resolved::NotFound("net x".into(), Vec::with_capacity(0))
+        );
+    }
 }
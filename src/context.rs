@@ -0,0 +1,197 @@
+//! Access to the REPL from inside of command handlers.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+use rustyline::history::History;
+
+use crate::command::Command;
+use crate::interrupt::CancellationToken;
+use crate::jobs::{Job, JobContext};
+use crate::repl::normalize_path;
+
+/// Help messages by group paths, the top level one under an empty path.
+pub(crate) type HelpPages = HashMap<String, String>;
+
+/// Modification of the REPL requested by a command handler.
+pub(crate) enum Change<'a> {
+    Add(String, Box<Command<'a>>),
+    Remove(String),
+    SetEnabled(String, bool),
+    /// Work of a command that can be executed in background
+    Job(Job),
+    /// Future returned by an async command handler
+    #[cfg(feature = "async")]
+    Pending(crate::async_repl::CommandFuture<'a>),
+}
+
+/// Handle to the REPL that is passed to command handlers.
+///
+/// It allows commands to modify the REPL, for example to change the prompt or to register
+/// new commands, e.g. a `connect` command can add device-specific commands after it succeeds:
+/// ```rust
+/// # use easy_repl::{Repl, CommandStatus, ReplContext, command};
+/// let mut repl = Repl::builder()
+///     .add("connect", command! {
+///         "Connect to a device",
+///         (ctx: &mut ReplContext, name: String) => |ctx: &mut ReplContext, name: String| {
+///             ctx.set_prompt(format!("{}> ", name));
+///             ctx.add_command("reset", command!("Reset the device", () => || Ok(CommandStatus::Done)));
+///             ctx.set_enabled("connect", false);
+///             Ok(CommandStatus::Done)
+///         }
+///     })
+///     .build()
+///     .expect("Failed to build REPL");
+/// ```
+///
+/// Changes to the set of commands are queued and applied after the
+/// handler returns, as the REPL cannot be modified while the command is being executed.
+/// Errors resulting from applying these changes (e.g. duplicate commands) are handled as
+/// if they were returned from the handler.
+pub struct ReplContext<'c, 'a> {
+    prompt: &'c mut String,
+    out: &'c mut dyn Write,
+    history: Option<&'c mut History>,
+    state: Option<&'c mut dyn Any>,
+    cancellation: CancellationToken,
+    input: Option<&'c [u8]>,
+    help: Option<&'c HelpPages>,
+    changes: Vec<Change<'a>>,
+}
+
+impl<'c, 'a> ReplContext<'c, 'a> {
+    pub(crate) fn new(
+        prompt: &'c mut String,
+        out: &'c mut dyn Write,
+        history: Option<&'c mut History>,
+        state: Option<&'c mut dyn Any>,
+        cancellation: CancellationToken,
+        input: Option<&'c [u8]>,
+        help: Option<&'c HelpPages>,
+    ) -> Self {
+        ReplContext {
+            prompt,
+            out,
+            history,
+            state,
+            cancellation,
+            input,
+            help,
+            changes: vec![],
+        }
+    }
+
+    pub(crate) fn into_changes(self) -> Vec<Change<'a>> {
+        self.changes
+    }
+
+    /// Current prompt string.
+    pub fn prompt(&self) -> &str {
+        self.prompt
+    }
+
+    /// Change the prompt string, will be used starting from the next line.
+    pub fn set_prompt<T: Into<String>>(&mut self, prompt: T) {
+        *self.prompt = prompt.into();
+    }
+
+    /// Output of the command, the writer configured in [`ReplBuilder::out`](crate::repl::ReplBuilder::out)
+    /// or a file if the output has been redirected, see [`ReplBuilder::with_redirection`](crate::repl::ReplBuilder::with_redirection).
+    pub fn out(&mut self) -> &mut dyn Write {
+        self.out
+    }
+
+    /// Output of the previous command in a pipeline, [`None`] if the command is not a part of one.
+    ///
+    /// Only available to commands declared with `reads_input: true`, see [`command!`](crate::command!).
+    pub fn input(&mut self) -> Option<&mut dyn BufRead> {
+        self.input.as_mut().map(|input| input as &mut dyn BufRead)
+    }
+
+    /// Editor history, [`None`] if the command is not executed by a [`Repl`](crate::Repl).
+    pub fn history(&mut self) -> Option<&mut History> {
+        self.history.as_deref_mut()
+    }
+
+    /// Application state set with [`ReplBuilder::with_state`](crate::repl::ReplBuilder::with_state),
+    /// [`None`] if it has not been set or is not of type `T`.
+    pub fn state<T: Any>(&mut self) -> Option<&mut T> {
+        self.state.as_deref_mut()?.downcast_mut()
+    }
+
+    /// Token cancelled when the user presses CTRL-C, see the [`interrupt`](crate::interrupt) module.
+    ///
+    /// It can be cloned and checked from other threads or async command futures.
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Take the state out of the context for the lifetime of the context, used by [`command!`](crate::command!).
+    #[doc(hidden)]
+    pub fn take_state<T: Any>(&mut self) -> anyhow::Result<&'c mut T> {
+        match self.state.take() {
+            Some(state) if (*state).is::<T>() => Ok(state.downcast_mut().unwrap()),
+            state => {
+                self.state = state;
+                Err(anyhow::anyhow!(
+                    "REPL state of type {} is not available",
+                    std::any::type_name::<T>()
+                ))
+            }
+        }
+    }
+
+    /// Set the job that will be executed after the handler returns, used by [`job_command!`](crate::job_command!).
+    #[doc(hidden)]
+    pub fn run_job<F>(&mut self, job: F)
+    where
+        F: FnOnce(&mut JobContext<'_>) -> anyhow::Result<crate::CommandStatus> + Send + 'static,
+    {
+        self.changes.push(Change::Job(Box::new(job)));
+    }
+
+    /// Set the future that will be awaited after the handler returns, used by [`async_command!`](crate::async_command!).
+    #[cfg(feature = "async")]
+    #[doc(hidden)]
+    pub fn run_async<F>(&mut self, future: F)
+    where
+        F: std::future::Future<Output = anyhow::Result<crate::CommandStatus>> + 'a,
+    {
+        self.changes.push(Change::Pending(Box::pin(future)));
+    }
+
+    /// Add a command, see [`Repl::add_command`](crate::Repl::add_command).
+    pub fn add_command(&mut self, name: &str, cmd: Command<'a>) {
+        self.changes.push(Change::Add(name.into(), Box::new(cmd)));
+    }
+
+    /// Remove a command or group, see [`Repl::remove_command`](crate::Repl::remove_command).
+    pub fn remove_command(&mut self, name: &str) {
+        self.changes.push(Change::Remove(name.into()));
+    }
+
+    /// Enable or disable a command or group, see [`Repl::set_enabled`](crate::Repl::set_enabled).
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        self.changes.push(Change::SetEnabled(name.into(), enabled));
+    }
+
+    /// Print the help message, or help for a group if `group` is not empty.
+    ///
+    /// The help is written to [`ReplContext::out`] immediately, so it does not include the
+    /// changes requested by the handler, as these are applied after it returns. Fails if the
+    /// command is not executed by a [`Repl`](crate::Repl), e.g. with [`Command::run`](crate::Command::run).
+    pub fn print_help(&mut self, group: &str) -> anyhow::Result<()> {
+        let pages = self
+            .help
+            .ok_or_else(|| anyhow::anyhow!("help is not available outside of a REPL"))?;
+        let help = pages
+            .get(&normalize_path(group))
+            .ok_or_else(|| anyhow::anyhow!("Group not found: {group}"))?;
+        writeln!(self.out, "{help}")?;
+        Ok(())
+    }
+}
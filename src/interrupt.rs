@@ -0,0 +1,288 @@
+//! Handling of CTRL-C.
+//!
+//! When [`ReplBuilder::catch_interrupts`](crate::repl::ReplBuilder::catch_interrupts) is enabled,
+//! pressing CTRL-C while a command is running does not kill the process, but cancels the
+//! [`CancellationToken`] available from [`ReplContext::cancellation`](crate::ReplContext::cancellation).
+//! Long-running handlers should poll it and return early, e.g. with [`CancellationToken::check`]:
+//! ```rust
+//! # use std::time::Duration;
+//! # use easy_repl::{Repl, CommandStatus, ReplContext, command};
+//! let mut repl = Repl::builder()
+//!     .add("count", command! {
+//!         "Count until interrupted",
+//!         (ctx: &mut ReplContext) => |ctx: &mut ReplContext| {
+//!             for i in 0.. {
+//!                 ctx.cancellation().check()?;
+//!                 writeln!(ctx.out(), "{}", i)?;
+//!                 std::thread::sleep(Duration::from_secs(1));
+//!             }
+//!             Ok(CommandStatus::Done)
+//!         }
+//!     })
+//!     .catch_interrupts(true)
+//!     .build()
+//!     .expect("Failed to build REPL");
+//! ```
+//!
+//! After an interrupted command the REPL prints `Interrupted` and returns to the prompt,
+//! the command is treated as failed. The signal handler is process-wide and is never
+//! uninstalled. When there is no command to cancel, e.g. after the REPL is dropped, or if the
+//! handler does not stop and CTRL-C is pressed again, the signal is passed to the disposition
+//! that was set before the handler has been installed. By default this terminates the process,
+//! but a handler installed earlier by the application (or ignoring SIGINT) keeps working.
+//!
+//! CTRL-C at the prompt discards the current line, unless neither interrupts are caught nor
+//! the default policy has been changed, in which case it quits the REPL. When the line is
+//! empty, the REPL behaves according to [`ReplBuilder::interrupt_policy`](crate::repl::ReplBuilder::interrupt_policy).
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex, OnceLock,
+};
+
+/// Token cancelled when the user presses CTRL-C during command execution.
+///
+/// It can be cloned and moved to other threads, all clones share the same state.
+/// The REPL resets the token after reporting the interrupted command.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the token, e.g. to interrupt the command from inside of the handler.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Returns [`Interrupted`] if the token has been cancelled, to be used with `?`.
+    pub fn check(&self) -> Result<(), Interrupted> {
+        if self.is_cancelled() {
+            Err(Interrupted)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Error returned by commands that stopped because of CTRL-C, see [`CancellationToken::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("interrupted")]
+pub struct Interrupted;
+
+/// What to do when CTRL-C is pressed at the prompt with an empty line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InterruptPolicy {
+    /// Quit the REPL
+    #[default]
+    Quit,
+    /// Show a new prompt
+    Clear,
+    /// Quit only if CTRL-C is pressed again right after the first one
+    DoublePress,
+}
+
+/// Tokens of commands being executed with ids of their guards, the last one is cancelled on CTRL-C
+static ACTIVE: Mutex<Vec<(u64, CancellationToken)>> = Mutex::new(vec![]);
+
+/// Disposition of SIGINT from before [`install_handler`], used when there is nothing to cancel
+#[cfg(unix)]
+static PREVIOUS: OnceLock<libc::sigaction> = OnceLock::new();
+
+/// Install the process-wide CTRL-C handler, only the first call has any effect.
+pub(crate) fn install_handler() -> Result<(), String> {
+    static INSTALLED: OnceLock<Result<(), String>> = OnceLock::new();
+    INSTALLED
+        .get_or_init(|| {
+            #[cfg(unix)]
+            save_previous();
+            ctrlc::set_handler(on_signal).map_err(|e| e.to_string())
+        })
+        .clone()
+}
+
+fn on_signal() {
+    if !cancel_last(&ACTIVE.lock().unwrap()) {
+        #[cfg(unix)]
+        match PREVIOUS.get() {
+            Some(previous) => raise_with(previous),
+            None => raise_with(&default_action()),
+        }
+        // console handlers cannot be removed, exit like the default one does
+        #[cfg(not(unix))]
+        std::process::exit(0xC000013Au32 as i32);
+    }
+}
+
+/// Cancel the token of the innermost command, returns `false` if there is none
+/// or the command has not stopped after the token has already been cancelled.
+fn cancel_last(active: &[(u64, CancellationToken)]) -> bool {
+    match active.last() {
+        Some((_, token)) if !token.is_cancelled() => {
+            token.cancel();
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(unix)]
+fn save_previous() {
+    // SAFETY: sigaction with a null new action only reads the current one
+    unsafe {
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(libc::SIGINT, std::ptr::null(), &mut previous) == 0 {
+            let _ = PREVIOUS.set(previous);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn default_action() -> libc::sigaction {
+    // SAFETY: all-zero sigaction is valid, with SIG_DFL as the handler
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = libc::SIG_DFL;
+    action
+}
+
+/// Raise SIGINT with `action` temporarily set as its disposition, then restore the current one.
+#[cfg(unix)]
+fn raise_with(action: &libc::sigaction) {
+    // SAFETY: the actions are valid, either zeroed or returned by sigaction, the signal
+    // is delivered to the calling thread before raise returns
+    unsafe {
+        let mut current: libc::sigaction = std::mem::zeroed();
+        libc::sigaction(libc::SIGINT, action, &mut current);
+        libc::raise(libc::SIGINT);
+        libc::sigaction(libc::SIGINT, &current, std::ptr::null_mut());
+    }
+}
+
+/// Routes CTRL-C to the token until dropped.
+pub(crate) struct ActiveGuard(u64);
+
+pub(crate) fn activate(token: &CancellationToken) -> ActiveGuard {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    ACTIVE.lock().unwrap().push((id, token.clone()));
+    ActiveGuard(id)
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        ACTIVE.lock().unwrap().retain(|(id, _)| *id != self.0);
+    }
+}
+
+/// Records whether the line was empty when CTRL-C was pressed in [`rustyline`].
+#[derive(Clone)]
+pub(crate) struct PromptInterrupt(Arc<AtomicBool>);
+
+impl PromptInterrupt {
+    pub(crate) fn new() -> Self {
+        PromptInterrupt(Arc::new(AtomicBool::new(true)))
+    }
+
+    pub(crate) fn was_empty(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_empty(&self, empty: bool) {
+        self.0.store(empty, Ordering::SeqCst);
+    }
+}
+
+impl rustyline::ConditionalEventHandler for PromptInterrupt {
+    fn handle(
+        &self,
+        _evt: &rustyline::Event,
+        _n: rustyline::RepeatCount,
+        _positive: bool,
+        ctx: &rustyline::EventContext,
+    ) -> Option<rustyline::Cmd> {
+        self.0.store(ctx.line().is_empty(), Ordering::SeqCst);
+        // use the default command, i.e. interrupt
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancellation_token() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(token.check().is_ok());
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(token.check(), Err(Interrupted));
+        token.reset();
+        assert!(!clone.is_cancelled());
+    }
+
+    #[test]
+    fn signal_cancels_active_token() {
+        let (outer, inner) = (CancellationToken::new(), CancellationToken::new());
+        let _outer = activate(&outer);
+        {
+            let _inner = activate(&inner);
+            on_signal();
+            assert!(inner.is_cancelled());
+        }
+        on_signal();
+        assert!(outer.is_cancelled());
+    }
+
+    #[test]
+    fn nothing_to_cancel() {
+        assert!(!cancel_last(&[]));
+        let token = CancellationToken::new();
+        assert!(cancel_last(&[(0, token.clone())]));
+        assert!(token.is_cancelled());
+        // the command did not stop
+        assert!(!cancel_last(&[(0, token)]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn previous_disposition_on_signal() {
+        static CALLED: AtomicBool = AtomicBool::new(false);
+        extern "C" fn handler(_: libc::c_int) {
+            CALLED.store(true, Ordering::SeqCst);
+        }
+        let query = || unsafe {
+            let mut current: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(libc::SIGINT, std::ptr::null(), &mut current);
+            current.sa_sigaction
+        };
+        let before = query();
+
+        // SIGINT ignored by the application does not terminate the process
+        let mut ignore = default_action();
+        ignore.sa_sigaction = libc::SIG_IGN;
+        raise_with(&ignore);
+        assert_eq!(query(), before);
+
+        // handler of the application is called instead of exiting
+        let mut action = default_action();
+        action.sa_sigaction = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        raise_with(&action);
+        assert!(CALLED.load(Ordering::SeqCst));
+        assert_eq!(query(), before);
+    }
+}
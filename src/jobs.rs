@@ -0,0 +1,506 @@
+//! Commands executed in background.
+//!
+//! When [`ReplBuilder::with_jobs`](crate::repl::ReplBuilder::with_jobs) is enabled, commands
+//! created with [`job_command!`](crate::job_command!) can be executed on a worker thread by
+//! adding `&` at the end of the line. The prompt returns immediately and the job is managed
+//! with the built-in commands:
+//! ```text
+//! > flash firmware.bin &
+//! [1] flash firmware.bin
+//! > jobs
+//! [1] Running  flash firmware.bin
+//! > wait 1
+//! Flashed 524288 bytes
+//! [1] Done  flash firmware.bin
+//! ```
+//!
+//! * `jobs` lists the jobs
+//! * `wait [ID...]` waits until the jobs finish, all of them if no ID is given
+//! * `kill ID...` cancels the jobs, see [`JobContext::cancellation`]
+//!
+//! Output of a background job is collected and printed when the job finishes. A job finishing
+//! while the user is editing a line is reported right away, above the prompt, without
+//! disturbing the line being edited. This requires the default [`rustyline`] line source
+//! reading from a terminal, otherwise finished jobs are reported before the next prompt
+//! (or by `jobs` and `wait`). Without `&` the job is executed in the REPL thread, as any
+//! other command. Jobs still running when the REPL is dropped are cancelled and waited for
+//! up to a second, their output is discarded. Jobs that do not stop by then, e.g. because they
+//! never check [`JobContext::cancellation`], are left running on their detached threads.
+
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+    panic::AssertUnwindSafe,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use rustyline::ExternalPrinter;
+
+use crate::command::CommandStatus;
+use crate::interrupt::{CancellationToken, Interrupted};
+
+/// Work of a command that can be executed in background, see [`job_command!`](crate::job_command!).
+pub type Job = Box<dyn FnOnce(&mut JobContext<'_>) -> anyhow::Result<CommandStatus> + Send>;
+
+/// Context passed to [`Job`]s, which may be executed on another thread.
+pub struct JobContext<'c> {
+    out: &'c mut dyn Write,
+    cancellation: CancellationToken,
+    input: Option<&'c [u8]>,
+}
+
+impl<'c> JobContext<'c> {
+    pub(crate) fn new(
+        out: &'c mut dyn Write,
+        cancellation: CancellationToken,
+        input: Option<&'c [u8]>,
+    ) -> Self {
+        JobContext {
+            out,
+            cancellation,
+            input,
+        }
+    }
+
+    /// Output of the job, for background jobs it is printed when the job finishes.
+    pub fn out(&mut self) -> &mut dyn Write {
+        self.out
+    }
+
+    /// Token cancelled on CTRL-C (in foreground) or by `kill` (in background).
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Output of the previous command in a pipeline, see [`ReplContext::input`](crate::ReplContext::input).
+    /// Always [`None`] in background.
+    pub fn input(&mut self) -> Option<&mut dyn BufRead> {
+        self.input.as_mut().map(|input| input as &mut dyn BufRead)
+    }
+}
+
+struct RunningJob {
+    line: String,
+    cancellation: CancellationToken,
+    handle: JoinHandle<()>,
+}
+
+/// Job that has finished, to be reported by the REPL.
+pub(crate) struct FinishedJob {
+    pub(crate) id: usize,
+    pub(crate) line: String,
+    pub(crate) output: Vec<u8>,
+    pub(crate) result: Result<(), String>,
+    killed: bool,
+}
+
+impl FinishedJob {
+    /// Output of the job followed by its status, as printed by the REPL.
+    pub(crate) fn report(&self) -> Vec<u8> {
+        let mut report = self.output.clone();
+        if !report.is_empty() && !report.ends_with(b"\n") {
+            report.push(b'\n');
+        }
+        report.extend_from_slice(format!("{self}\n").as_bytes());
+        report
+    }
+}
+
+impl std::fmt::Display for FinishedJob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.result {
+            Ok(()) => write!(f, "[{}] Done  {}", self.id, self.line),
+            Err(_) if self.killed => write!(f, "[{}] Killed  {}", self.id, self.line),
+            Err(err) => write!(f, "[{}] Failed  {}: {}", self.id, self.line, err),
+        }
+    }
+}
+
+/// Prints jobs that finish while the REPL is reading a line, see [`Jobs::set_reading`].
+#[derive(Default)]
+struct Reporter {
+    printer: Option<Box<dyn ExternalPrinter + Send>>,
+    reading: bool,
+}
+
+/// Finished job with `true` if it has already been reported by the [`Reporter`].
+type Done = (FinishedJob, bool);
+
+/// How long dropping [`Jobs`] waits for the cancelled jobs to finish.
+const DROP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Background jobs of a REPL.
+///
+/// When dropped, all the jobs are cancelled and their threads are joined, unless they do not
+/// finish within [`DROP_TIMEOUT`].
+pub(crate) struct Jobs {
+    last_id: usize,
+    running: BTreeMap<usize, RunningJob>,
+    /// Jobs that have finished, sent at the end of the job thread
+    done: (Sender<Done>, Receiver<Done>),
+    /// Jobs received from `done` that have not been removed yet
+    received: BTreeMap<usize, Done>,
+    reporter: Arc<Mutex<Reporter>>,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Jobs {
+            last_id: 0,
+            running: BTreeMap::new(),
+            done: mpsc::channel(),
+            received: BTreeMap::new(),
+            reporter: Default::default(),
+        }
+    }
+}
+
+impl Drop for Jobs {
+    fn drop(&mut self) {
+        for job in self.running.values() {
+            job.cancellation.cancel();
+        }
+        let deadline = Instant::now() + DROP_TIMEOUT;
+        self.receive();
+        while self.received.len() < self.running.len() {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.done.1.recv_timeout(timeout) {
+                Ok((job, reported)) => {
+                    self.received.insert(job.id, (job, reported));
+                }
+                Err(_) => break,
+            }
+        }
+        for (id, job) in std::mem::take(&mut self.running) {
+            // threads of jobs that ignore the cancellation are detached
+            if self.received.contains_key(&id) {
+                let _ = job.handle.join();
+            }
+        }
+    }
+}
+
+impl Jobs {
+    /// Start the job on a new thread and return its ID. Output is collected unless `out` is given.
+    pub(crate) fn spawn(
+        &mut self,
+        line: &str,
+        job: Job,
+        out: Option<Box<dyn Write + Send>>,
+    ) -> anyhow::Result<usize> {
+        let id = self.last_id + 1;
+        let line = line.trim().to_string();
+        let cancellation = CancellationToken::new();
+        let token = cancellation.clone();
+        let (done, reporter) = (self.done.0.clone(), self.reporter.clone());
+        let finished_line = line.clone();
+        let handle = std::thread::Builder::new()
+            .name(format!("job-{id}"))
+            .spawn(move || {
+                let context_token = token.clone();
+                let run = AssertUnwindSafe(move || match out {
+                    Some(mut out) => {
+                        let result = job(&mut JobContext::new(&mut out, context_token, None));
+                        let result = result.and_then(|status| Ok(out.flush().map(|_| status)?));
+                        (result, vec![])
+                    }
+                    None => {
+                        let mut out = vec![];
+                        let result = job(&mut JobContext::new(&mut out, context_token, None));
+                        (result, out)
+                    }
+                });
+                let (result, output) = match std::panic::catch_unwind(run) {
+                    Ok((result, output)) => (result.map(|_| ()).map_err(|e| e.to_string()), output),
+                    Err(_) => (Err("job panicked".to_string()), vec![]),
+                };
+                let job = FinishedJob {
+                    id,
+                    line: finished_line,
+                    output,
+                    result,
+                    killed: token.is_cancelled(),
+                };
+                let mut reporter = reporter.lock().unwrap();
+                let reported = reporter.reading
+                    && reporter.printer.as_mut().is_some_and(|printer| {
+                        let report = String::from_utf8_lossy(&job.report()).into_owned();
+                        printer.print(report).is_ok()
+                    });
+                // the receiver may be gone if the REPL is being dropped
+                let _ = done.send((job, reported));
+            })?;
+        self.last_id = id;
+        self.running.insert(
+            id,
+            RunningJob {
+                line,
+                cancellation,
+                handle,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Set the printer used to report jobs that finish while the REPL is reading a line.
+    pub(crate) fn set_printer(&mut self, printer: Box<dyn ExternalPrinter + Send>) {
+        self.reporter.lock().unwrap().printer = Some(printer);
+    }
+
+    /// Mark the start or the end of reading a line. Jobs finishing in between are reported
+    /// with the printer, if there is one, and are removed when reading ends.
+    pub(crate) fn set_reading(&mut self, reading: bool) {
+        self.reporter.lock().unwrap().reading = reading;
+        if !reading {
+            self.receive();
+            let reported: Vec<_> = self
+                .received
+                .iter()
+                .filter(|(_, (_, reported))| *reported)
+                .map(|(id, _)| *id)
+                .collect();
+            for id in reported {
+                self.remove(id);
+            }
+        }
+    }
+
+    /// IDs and command lines of all jobs, with `true` for jobs that are still running.
+    pub(crate) fn list(&self) -> Vec<(usize, &str, bool)> {
+        self.running
+            .iter()
+            .map(|(id, job)| (*id, job.line.as_str(), !job.handle.is_finished()))
+            .collect()
+    }
+
+    pub(crate) fn contains(&self, id: usize) -> bool {
+        self.running.contains_key(&id)
+    }
+
+    /// Cancel the job, returns `false` if there is no such job.
+    pub(crate) fn kill(&mut self, id: usize) -> bool {
+        match self.running.get(&id) {
+            Some(job) => {
+                job.cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove jobs that have finished, returns the ones that have not been reported yet.
+    pub(crate) fn finished(&mut self) -> Vec<FinishedJob> {
+        self.receive();
+        let ids: Vec<_> = self.received.keys().copied().collect();
+        ids.into_iter()
+            .map(|id| self.remove(id))
+            .filter(|(_, reported)| !reported)
+            .map(|(job, _)| job)
+            .collect()
+    }
+
+    /// Wait until the job finishes, unless `cancellation` is cancelled first.
+    pub(crate) fn wait(
+        &mut self,
+        id: usize,
+        cancellation: &CancellationToken,
+    ) -> Result<FinishedJob, Interrupted> {
+        // woken up when a job finishes, the timeout only limits how long it takes
+        // to notice that the wait has been cancelled
+        const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+        while !self.received.contains_key(&id) {
+            cancellation.check()?;
+            if let Ok((job, reported)) = self.done.1.recv_timeout(CHECK_INTERVAL) {
+                self.received.insert(job.id, (job, reported));
+            }
+        }
+        Ok(self.remove(id).0)
+    }
+
+    /// Move jobs that have finished from the channel to `received`.
+    fn receive(&mut self) {
+        while let Ok((job, reported)) = self.done.1.try_recv() {
+            self.received.insert(job.id, (job, reported));
+        }
+    }
+
+    /// Remove a job that has been received, its thread is exiting.
+    fn remove(&mut self, id: usize) -> Done {
+        let job = self.running.remove(&id).unwrap();
+        // the job has already finished, a panic has been caught
+        let _ = job.handle.join();
+        self.received.remove(&id).unwrap()
+    }
+}
+
+/// Generate a [`Command`](crate::Command) that can be executed in background.
+///
+/// The syntax is the same as for [`command!`](crate::command!), but the handler has to return
+/// a [`Job`], i.e. a closure `FnOnce(&mut JobContext) -> anyhow::Result<CommandStatus> + Send`.
+/// Arguments are parsed in the REPL thread before the handler is called, and the returned closure
+/// is executed in the REPL thread or, if the line ends with `&`, on a worker thread:
+/// ```rust
+/// # use std::time::Duration;
+/// # use easy_repl::{CommandStatus, job_command, jobs::JobContext};
+/// let mut cmd = job_command! {
+///     "Capture the log",
+///     (seconds: u64) => |seconds| move |job: &mut JobContext| {
+///         for _ in 0..seconds {
+///             job.cancellation().check()?;
+///             std::thread::sleep(Duration::from_millis(1));
+///         }
+///         writeln!(job.out(), "Log captured")?;
+///         Ok(CommandStatus::Done)
+///     }
+/// };
+/// assert!(cmd.background);
+/// assert!(cmd.run(&["3"]).is_ok());
+/// ```
+///
+/// [`CommandStatus::Quit`] returned from a background job is ignored.
+#[macro_export]
+macro_rules! job_command {
+    ($description:expr, $($key:ident : $value:expr,)* ( $($args:tt)* ) => $handler:expr $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut cmd = $crate::command!(@context job $description, ( $($args)* ) => $handler);
+        $( $crate::command!(@set cmd, $key, $value); )*
+        cmd
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(result: anyhow::Result<CommandStatus>) -> Job {
+        Box::new(move |ctx: &mut JobContext| {
+            write!(ctx.out(), "output")?;
+            result
+        })
+    }
+
+    #[test]
+    fn jobs_finish() {
+        let mut jobs = Jobs::default();
+        let ok = jobs
+            .spawn("ok 1 ", job(Ok(CommandStatus::Done)), None)
+            .unwrap();
+        let err = jobs
+            .spawn("err", job(Err(anyhow::anyhow!("boom"))), None)
+            .unwrap();
+        assert_eq!((ok, err), (1, 2));
+        assert_eq!(jobs.list()[0].1, "ok 1");
+
+        let token = CancellationToken::new();
+        let finished = jobs.wait(ok, &token).unwrap();
+        assert_eq!(finished.to_string(), "[1] Done  ok 1");
+        assert_eq!(finished.output, b"output");
+        assert!(!jobs.contains(ok));
+        let finished = jobs.wait(err, &token).unwrap();
+        assert_eq!(finished.to_string(), "[2] Failed  err: boom");
+        assert!(jobs.list().is_empty());
+        assert_eq!(
+            jobs.spawn("next", job(Ok(CommandStatus::Done)), None)
+                .unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn jobs_kill() {
+        let mut jobs = Jobs::default();
+        let id = jobs
+            .spawn(
+                "loop",
+                Box::new(|ctx: &mut JobContext| loop {
+                    ctx.cancellation().check()?;
+                    std::thread::yield_now();
+                }),
+                None,
+            )
+            .unwrap();
+        assert_eq!(jobs.list(), [(id, "loop", true)]);
+
+        let token = CancellationToken::new();
+        token.cancel();
+        assert_eq!(jobs.wait(id, &token).err(), Some(Interrupted));
+        assert!(jobs.kill(id));
+        assert!(!jobs.kill(id + 1));
+        let finished = jobs.wait(id, &CancellationToken::new()).unwrap();
+        assert_eq!(finished.to_string(), "[1] Killed  loop");
+    }
+
+    #[test]
+    fn jobs_reported_while_reading() {
+        #[derive(Clone, Default)]
+        struct Printer(Arc<Mutex<Vec<String>>>);
+        impl ExternalPrinter for Printer {
+            fn print(&mut self, msg: String) -> rustyline::Result<()> {
+                self.0.lock().unwrap().push(msg);
+                Ok(())
+            }
+        }
+
+        let printer = Printer::default();
+        let mut jobs = Jobs::default();
+        jobs.set_printer(Box::new(printer.clone()));
+        jobs.set_reading(true);
+        let id = jobs
+            .spawn("ok", job(Ok(CommandStatus::Done)), None)
+            .unwrap();
+        while printer.0.lock().unwrap().is_empty() {
+            std::thread::yield_now();
+        }
+        jobs.set_reading(false);
+        assert_eq!(*printer.0.lock().unwrap(), ["output\n[1] Done  ok\n"]);
+        // already reported
+        assert!(!jobs.contains(id));
+        assert!(jobs.finished().is_empty());
+
+        // reported by the REPL before the next prompt
+        let id = jobs
+            .spawn("later", job(Ok(CommandStatus::Done)), None)
+            .unwrap();
+        jobs.wait(id, &CancellationToken::new()).unwrap();
+        assert_eq!(printer.0.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn jobs_drop() {
+        let stopped = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = stopped.clone();
+        let mut jobs = Jobs::default();
+        let job = move |ctx: &mut JobContext| loop {
+            if ctx.cancellation().is_cancelled() {
+                flag.store(true, std::sync::atomic::Ordering::SeqCst);
+                return Ok(CommandStatus::Done);
+            }
+            std::thread::yield_now();
+        };
+        jobs.spawn("loop", Box::new(job), None).unwrap();
+        drop(jobs);
+        assert!(stopped.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn jobs_drop_timeout() {
+        let (release, released) = mpsc::channel::<()>();
+        let mut jobs = Jobs::default();
+        // ignores the cancellation
+        let job = move |_: &mut JobContext| {
+            let _ = released.recv();
+            Ok(CommandStatus::Done)
+        };
+        jobs.spawn("stuck", Box::new(job), None).unwrap();
+        let start = Instant::now();
+        drop(jobs);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= DROP_TIMEOUT && elapsed < 5 * DROP_TIMEOUT);
+        release.send(()).unwrap();
+    }
+}
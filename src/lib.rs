@@ -63,7 +63,7 @@
 //! Usage: add X:i32 Y:i32
 //! ```
 //!
-//! It includes automatic `help` and `quit` commands. The help message is auto-generated:
+//! It includes automatic `help`, `quit` and `source` commands. The help message is auto-generated:
 //! ```text
 //! > help
 //! Available commands:
@@ -71,10 +71,14 @@
 //!   hello name:String  Say hello
 //!
 //! Other commands:
-//!   help  Show this help message
-//!   quit  Quit repl
+//!   help    Show this help message
+//!   quit    Quit repl
+//!   source  Execute commands from a file
 //! ```
 //!
+//! Use `help <command>` to show all the details of a command, including argument descriptions,
+//! long description and usage examples (see [`command!`]).
+//!
 //! By default user does not have to use full command names, if the command name can be
 //! resloved unambigiously (i.e. prefix matches only a single command), e.g.
 //! ```text
@@ -92,12 +96,49 @@
 //! ```
 //!
 //! The REPL also by default automatically implements command hints and TAB-completion (see [`rustyline::hint`], [`rustyline::completion`]).
+//! Command arguments are completed too, depending on their types or using completers
+//! specified in [`command!`], see the [`completion`] module.
+//!
+//! Long-running commands can be interrupted with CTRL-C without killing the process,
+//! see the [`interrupt`] module, and commands created with [`job_command!`] can be executed
+//! in background, see the [`jobs`] module. Output of a command can also be redirected to a file
+//! with `> path` or `>> path`, see [`ReplBuilder::with_redirection`](repl::ReplBuilder::with_redirection),
+//! or passed to other commands, e.g. `logs | grep ERROR | head 20`, see
+//! [`ReplBuilder::with_pipelines`](repl::ReplBuilder::with_pipelines).
+//!
+//! # Features
+//!
+//! * `derive` (enabled by default) - provides `#[derive(ReplArgs)]` that can be used with
+//!   [`Command::from_args`] for commands with many arguments, see `examples/derive.rs`,
+//!   and `#[derive(Choice)]` for enum arguments, see [`Choice`].
+//! * `async` - provides [`AsyncRepl`] and [`async_command!`] for commands
+//!   with async handlers, running on [tokio](https://tokio.rs), see the `async_repl` module
+//!   and `examples/async.rs`.
+
+// allows to use derive macros inside this crate
+#[cfg(test)]
+extern crate self as easy_repl;
 
+#[cfg(feature = "async")]
+pub mod async_repl;
+mod builtin;
 pub mod command;
-mod completion;
+pub mod completion;
+pub mod context;
+pub mod interrupt;
+pub mod jobs;
+mod line;
 pub mod repl;
+pub mod source;
+pub mod testing;
 
 pub use anyhow;
 
-pub use command::{Command, CommandStatus, Critical, CriticalError};
+#[cfg(feature = "async")]
+pub use async_repl::AsyncRepl;
+pub use command::{Choice, Command, CommandStatus, Critical, CriticalError, ReplArgs};
+pub use context::ReplContext;
+#[cfg(feature = "derive")]
+pub use easy_repl_derive::{Choice, ReplArgs};
 pub use repl::Repl;
+pub use source::LineSource;
@@ -0,0 +1,543 @@
+//! Splitting input lines into separate commands and expanding variables.
+
+use crate::repl::LoopStatus;
+
+/// Condition for executing a command in a chain, depends on the operator before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Chain {
+    /// First command or after `;`
+    Always,
+    /// After `&&`, executed if the previous command succeeded
+    IfSuccess,
+    /// After `||`, executed if the previous command failed
+    IfFailure,
+}
+
+impl Chain {
+    /// Whether the command should be executed, depending on the result of the previous one.
+    pub(crate) fn executes(&self, previous_ok: bool) -> bool {
+        match self {
+            Chain::Always => true,
+            Chain::IfSuccess => previous_ok,
+            Chain::IfFailure => !previous_ok,
+        }
+    }
+
+    fn operator(&self) -> &'static str {
+        match self {
+            Chain::Always => ";",
+            Chain::IfSuccess => "&&",
+            Chain::IfFailure => "||",
+        }
+    }
+}
+
+/// Commands of a line that are executed one after another, depending on the results
+/// of the previous ones.
+pub(crate) struct ChainExecution<'l> {
+    commands: std::iter::Peekable<std::vec::IntoIter<(Chain, &'l str)>>,
+    /// Result of the last command that has been executed
+    result: Result<LoopStatus, String>,
+}
+
+impl<'l> ChainExecution<'l> {
+    pub(crate) fn new(commands: Vec<(Chain, &'l str)>) -> Self {
+        ChainExecution {
+            commands: commands.into_iter().peekable(),
+            result: Ok(LoopStatus::Continue),
+        }
+    }
+
+    /// Next command that should be executed, [`None`] if there are no more or the REPL should quit.
+    pub(crate) fn next_command(&mut self) -> Option<&'l str> {
+        if self.result == Ok(LoopStatus::Break) {
+            return None;
+        }
+        let previous_ok = self.result.is_ok();
+        self.commands
+            .find(|(chain, _)| chain.executes(previous_ok))
+            .map(|(_, command)| command)
+    }
+
+    /// Whether the current command is the last one on the line, otherwise its result
+    /// is needed before the next command.
+    pub(crate) fn is_last(&mut self) -> bool {
+        self.commands.peek().is_none()
+    }
+
+    /// Set the result of the current command.
+    pub(crate) fn set_result(&mut self, result: Result<LoopStatus, String>) {
+        self.result = result;
+    }
+
+    /// Result of the last executed command.
+    pub(crate) fn result(self) -> Result<LoopStatus, String> {
+        self.result
+    }
+}
+
+/// How a character of a line is interpreted, following the rules used by [`shell_words`]
+/// so that each command can later be split into arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    /// Outside of quotes, can be a part of an operator
+    Unquoted,
+    /// Inside double quotes, where variables are expanded
+    Double,
+    /// Inside single quotes, escaped with a backslash, or a quote or backslash itself
+    Literal,
+}
+
+/// Characters of a line with their [`Quoting`], ends at a comment.
+struct Tokens<'l> {
+    chars: std::str::CharIndices<'l>,
+    quote: Option<char>,
+    escaped: bool,
+    word_start: bool,
+}
+
+impl<'l> Tokens<'l> {
+    fn new(line: &'l str) -> Self {
+        Tokens {
+            chars: line.char_indices(),
+            quote: None,
+            escaped: false,
+            word_start: true,
+        }
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = (usize, char, Quoting);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, c) = self.chars.next()?;
+        let quoting = match (self.quote, c) {
+            _ if self.escaped => {
+                self.escaped = false;
+                Quoting::Literal
+            }
+            (Some(quote), c) if c == quote => {
+                self.quote = None;
+                Quoting::Literal
+            }
+            (Some('"') | None, '\\') => {
+                self.escaped = true;
+                Quoting::Literal
+            }
+            (Some('"'), _) => Quoting::Double,
+            (Some(_), _) => Quoting::Literal,
+            (None, '\'' | '"') => {
+                self.quote = Some(c);
+                Quoting::Literal
+            }
+            // comment, ignored by shell_words
+            (None, '#') if self.word_start => {
+                self.chars = "".char_indices();
+                return None;
+            }
+            (None, _) => Quoting::Unquoted,
+        };
+        // operators also separate words, e.g. `a;#b` is a command followed by a comment
+        self.word_start = quoting == Quoting::Unquoted
+            && (c.is_whitespace() || matches!(c, ';' | '&' | '|' | '>'));
+        Some((i, c, quoting))
+    }
+}
+
+/// Positions of characters that are not quoted, escaped or a part of a comment.
+fn unquoted(line: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    Tokens::new(line)
+        .filter(|(_, _, quoting)| *quoting == Quoting::Unquoted)
+        .map(|(i, c, _)| (i, c))
+}
+
+/// Split line into commands separated by `;`, `&&` and `||`.
+///
+/// Operators inside quotes or escaped with a backslash are ignored, see [`Tokens`].
+/// Empty commands are skipped, unless they are used with `&&` or `||`.
+pub(crate) fn split_chain(line: &str) -> Result<Vec<(Chain, &str)>, String> {
+    let mut commands = vec![];
+    let mut push = |chain: Chain, next: Chain, range: std::ops::Range<usize>| {
+        let cmd = &line[range];
+        if !cmd.trim().is_empty() {
+            commands.push((chain, cmd));
+        } else if chain != Chain::Always || next != Chain::Always {
+            let op = if chain != Chain::Always { chain } else { next };
+            return Err(format!("syntax error near '{}'", op.operator()));
+        }
+        Ok(())
+    };
+
+    let mut chain = Chain::Always;
+    let mut start = 0;
+    let mut chars = unquoted(line).peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = match c {
+            ';' => Chain::Always,
+            '&' | '|' if chars.next_if_eq(&(i + 1, c)).is_some() => {
+                if c == '&' {
+                    Chain::IfSuccess
+                } else {
+                    Chain::IfFailure
+                }
+            }
+            _ => continue,
+        };
+        push(chain, next, start..i)?;
+        chain = next;
+        start = i + next.operator().len();
+    }
+    push(chain, Chain::Always, start..line.len())?;
+    Ok(commands)
+}
+
+/// Split command into stages of a pipeline separated with `|`.
+///
+/// Operators inside quotes or escaped with a backslash are ignored, `||` is not a pipe.
+pub(crate) fn split_pipeline(command: &str) -> Result<Vec<&str>, String> {
+    let mut stages = vec![];
+    let mut push = |range: std::ops::Range<usize>| {
+        let stage = &command[range];
+        if stage.trim().is_empty() {
+            return Err("syntax error near '|'".to_string());
+        }
+        stages.push(stage);
+        Ok(())
+    };
+
+    let mut start = 0;
+    let mut chars = unquoted(command).peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '|' && chars.next_if_eq(&(i + 1, '|')).is_none() {
+            push(start..i)?;
+            start = i + 1;
+        }
+    }
+    push(start..command.len())?;
+    Ok(stages)
+}
+
+/// Strip the trailing `&` that requests executing the command in background.
+///
+/// Returns [`None`] if the command does not end with `&`, quoted or escaped `&` is ignored.
+pub(crate) fn strip_background(command: &str) -> Option<&str> {
+    // position of the `&` that is not followed by anything else
+    let mut background = None;
+    for (i, c, quoting) in Tokens::new(command) {
+        // `&&` is not a background operator
+        let operator = quoting == Quoting::Unquoted && c == '&' && !command[..i].ends_with('&');
+        if operator && background.is_none() {
+            background = Some(i);
+        } else if !c.is_whitespace() {
+            background = None;
+        }
+    }
+    background.map(|i| &command[..i])
+}
+
+/// Output redirection of a command, `> path` or `>> path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Redirect {
+    pub(crate) path: String,
+    /// `>>`, append to the file instead of truncating it
+    pub(crate) append: bool,
+}
+
+impl Redirect {
+    pub(crate) fn open(&self) -> std::io::Result<std::fs::File> {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(self.append)
+            .truncate(!self.append)
+            .open(&self.path)
+    }
+}
+
+/// Split the command into the part before `>` or `>>` and the redirection.
+///
+/// Operators inside quotes or escaped with a backslash are ignored, the file name is
+/// parsed with [`shell_words`] and it has to be the only word after the operator.
+pub(crate) fn split_redirect(command: &str) -> Result<(&str, Option<Redirect>), String> {
+    let i = match unquoted(command).find(|(_, c)| *c == '>') {
+        Some((i, _)) => i,
+        None => return Ok((command, None)),
+    };
+    let append = command[i + 1..].starts_with('>');
+    let op = if append { ">>" } else { ">" };
+    let words = shell_words::split(&command[i + op.len()..]).map_err(|err| err.to_string())?;
+    match <[String; 1]>::try_from(words) {
+        Ok([path]) => Ok((&command[..i], Some(Redirect { path, append }))),
+        Err(_) => Err(format!("expected a single file name after '{op}'")),
+    }
+}
+
+/// Check if the name can be used as a variable name, i.e. it is a valid identifier
+pub(crate) fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace `$NAME` and `${NAME}` with variable values returned by `lookup`.
+///
+/// Variables are expanded outside of quotes and inside double quotes, `$` can be escaped
+/// with a backslash. Values are quoted, so that each one remains a part of a single argument
+/// when the line is split using [`shell_words`]. A `$` not followed by a name is left as is.
+pub(crate) fn expand_variables<F>(line: &str, lookup: F) -> Result<String, String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut out = String::with_capacity(line.len());
+    // end of the part of the line that has already been copied to `out`
+    let mut copied = 0;
+    for (i, c, quoting) in Tokens::new(line) {
+        if c != '$' || quoting == Quoting::Literal {
+            continue;
+        }
+        let rest = &line[i + 1..];
+        let (name, len) = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| "missing closing '}' in variable name".to_string())?;
+            let name = &braced[..end];
+            if !is_variable_name(name) {
+                return Err(format!("invalid variable name: '{name}'"));
+            }
+            (name, end + 2)
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], end)
+        };
+        if !is_variable_name(name) {
+            continue;
+        }
+        let value = lookup(name).ok_or_else(|| format!("undefined variable: {name}"))?;
+        out.push_str(&line[copied..i]);
+        if quoting == Quoting::Double {
+            for c in value.chars() {
+                if matches!(c, '\\' | '"' | '$' | '`') {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+        } else {
+            out.push_str(&shell_words::quote(&value));
+        }
+        copied = i + 1 + len;
+    }
+    out.push_str(&line[copied..]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_quoting() {
+        use Quoting::*;
+        let kinds = |line| {
+            Tokens::new(line)
+                .map(|(_, c, quoting)| (c, quoting))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kinds(r#"a'$'"$\"""#),
+            [
+                ('a', Unquoted),
+                ('\'', Literal),
+                ('$', Literal),
+                ('\'', Literal),
+                ('"', Literal),
+                ('$', Double),
+                ('\\', Literal),
+                ('"', Literal),
+                ('"', Literal),
+            ]
+        );
+        assert_eq!(kinds(r"\;"), [('\\', Literal), (';', Literal)]);
+        assert_eq!(kinds("a#b #c"), kinds("a#b "));
+        assert_eq!(kinds("a;#b"), kinds("a;"));
+        assert_eq!(kinds(r"a \#b").len(), 5);
+    }
+
+    #[test]
+    fn chain_operators() {
+        use Chain::*;
+        assert_eq!(
+            split_chain("a 1; b && c||d").unwrap(),
+            vec![
+                (Always, "a 1"),
+                (Always, " b "),
+                (IfSuccess, " c"),
+                (IfFailure, "d")
+            ]
+        );
+        assert_eq!(split_chain("; a;;").unwrap(), vec![(Always, " a")]);
+        assert_eq!(
+            split_chain("a & b | c").unwrap(),
+            vec![(Always, "a & b | c")]
+        );
+        assert_eq!(split_chain("a && ").unwrap_err(), "syntax error near '&&'");
+        assert_eq!(split_chain("|| a").unwrap_err(), "syntax error near '||'");
+        assert_eq!(
+            split_chain("a; && b").unwrap_err(),
+            "syntax error near '&&'"
+        );
+    }
+
+    #[test]
+    fn chain_execution() {
+        fn run<'l>(line: &'l str, fails: &[&str]) -> (Vec<(&'l str, bool)>, bool) {
+            let mut chain = ChainExecution::new(split_chain(line).unwrap());
+            let mut executed = vec![];
+            while let Some(command) = chain.next_command().map(str::trim) {
+                executed.push((command, chain.is_last()));
+                chain.set_result(match command {
+                    "quit" => Ok(LoopStatus::Break),
+                    c if fails.contains(&c) => Err("failed".into()),
+                    _ => Ok(LoopStatus::Continue),
+                });
+            }
+            (executed, chain.result().is_ok())
+        }
+        assert_eq!(
+            run("a && b || c; d", &["b"]),
+            (
+                vec![("a", false), ("b", false), ("c", false), ("d", true)],
+                true
+            )
+        );
+        assert_eq!(
+            run("a || b && c", &[]),
+            (vec![("a", false), ("c", true)], true)
+        );
+        assert_eq!(
+            run("a; quit; b", &[]),
+            (vec![("a", false), ("quit", false)], true)
+        );
+        assert_eq!(run("a && b", &["a"]), (vec![("a", false)], false));
+    }
+
+    #[test]
+    fn chain_quoting() {
+        use Chain::*;
+        let quoted = r#"a ';' "&&" \|| "x\";" ; b"#;
+        assert_eq!(
+            split_chain(quoted).unwrap(),
+            vec![(Always, r#"a ';' "&&" \|| "x\";" "#), (Always, " b")]
+        );
+        assert_eq!(split_chain("a 'x;").unwrap(), vec![(Always, "a 'x;")]);
+        assert_eq!(split_chain("a # b; c").unwrap(), vec![(Always, "a # b; c")]);
+        assert_eq!(
+            split_chain("a#b; c").unwrap(),
+            vec![(Always, "a#b"), (Always, " c")]
+        );
+    }
+
+    #[test]
+    fn pipeline_stages() {
+        assert_eq!(
+            split_pipeline("logs | grep ERROR|head 20").unwrap(),
+            ["logs ", " grep ERROR", "head 20"]
+        );
+        assert_eq!(split_pipeline("logs").unwrap(), ["logs"]);
+        assert_eq!(
+            split_pipeline(r#"echo '|' "a|b" \| x || y"#).unwrap(),
+            [r#"echo '|' "a|b" \| x || y"#]
+        );
+        assert_eq!(split_pipeline("a # | b").unwrap(), ["a # | b"]);
+        assert_eq!(split_pipeline("a | ").unwrap_err(), "syntax error near '|'");
+        assert_eq!(split_pipeline("|a").unwrap_err(), "syntax error near '|'");
+    }
+
+    #[test]
+    fn background_commands() {
+        assert_eq!(strip_background("flash fw.bin &"), Some("flash fw.bin "));
+        assert_eq!(strip_background("flash&  "), Some("flash"));
+        assert_eq!(strip_background("a & # comment"), Some("a "));
+        assert_eq!(strip_background("a & b"), None);
+        assert_eq!(strip_background("a &&"), None);
+        assert_eq!(strip_background("a & &"), None);
+        assert_eq!(strip_background("a '&'"), None);
+        assert_eq!(strip_background(r#"a \& "x &""#), None);
+        assert_eq!(strip_background("a #&"), None);
+    }
+
+    #[test]
+    fn redirections() {
+        let redirect = |path: &str, append| {
+            let path = path.to_string();
+            Some(Redirect { path, append })
+        };
+        assert_eq!(
+            split_redirect("dump > out.txt").unwrap(),
+            ("dump ", redirect("out.txt", false))
+        );
+        assert_eq!(
+            split_redirect("dump 1>>'my log.txt' # c").unwrap(),
+            ("dump 1", redirect("my log.txt", true))
+        );
+        assert_eq!(
+            split_redirect(r#"echo '>' ">" \> a"#).unwrap(),
+            (r#"echo '>' ">" \> a"#, None)
+        );
+        assert_eq!(split_redirect("echo # > a").unwrap(), ("echo # > a", None));
+        assert_eq!(
+            split_redirect("dump >").unwrap_err(),
+            "expected a single file name after '>'"
+        );
+        assert_eq!(
+            split_redirect("dump >> a b").unwrap_err(),
+            "expected a single file name after '>>'"
+        );
+        assert_eq!(
+            split_redirect("dump > a > b").unwrap_err(),
+            "expected a single file name after '>'"
+        );
+    }
+
+    #[test]
+    fn variable_names() {
+        assert!(is_variable_name("addr_1"));
+        assert!(is_variable_name("_x"));
+        assert!(!is_variable_name("1x"));
+        assert!(!is_variable_name("a-b"));
+        assert!(!is_variable_name(""));
+    }
+
+    #[test]
+    fn variables_expansion() {
+        let lookup = |name: &str| match name {
+            "A" => Some("1".to_string()),
+            "B" => Some("x y".to_string()),
+            "Q" => Some(r#"a"$b"#.to_string()),
+            _ => None,
+        };
+        let expand = |line| expand_variables(line, lookup);
+        let split = |line| shell_words::split(&expand(line).unwrap()).unwrap();
+        assert_eq!(expand("cmd $A ${A}2 $B").unwrap(), "cmd 1 12 'x y'");
+        assert_eq!(
+            split("cmd $B x$B \"$Q\""),
+            ["cmd", "x y", "xx y", r#"a"$b"#]
+        );
+        assert_eq!(
+            split(r#"cmd '$A' \$A "\$A" $ 5$"#),
+            ["cmd", "$A", "$A", "$A", "$", "5$"]
+        );
+        assert_eq!(split("cmd $A # $C"), ["cmd", "1"]);
+        assert_eq!(expand("cmd $C").unwrap_err(), "undefined variable: C");
+        assert_eq!(
+            expand("cmd ${A").unwrap_err(),
+            "missing closing '}' in variable name"
+        );
+        assert_eq!(
+            expand("cmd ${1}").unwrap_err(),
+            "invalid variable name: '1'"
+        );
+    }
+}
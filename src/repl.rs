@@ -1,38 +1,135 @@
 //! Main REPL logic.
 
-use std::{collections::HashMap, io::Write, rc::Rc};
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+};
 
-use rustyline::{self, completion::FilenameCompleter, error::ReadlineError};
+use rustyline::{self, completion::FilenameCompleter, config::Configurer, error::ReadlineError};
 use shell_words;
 use textwrap;
 use thiserror;
-use trie_rs::{Trie, TrieBuilder};
 
+#[cfg(feature = "async")]
+use crate::async_repl::CommandFuture;
+use crate::builtin;
 use crate::command::{ArgsError, Command, CommandStatus, CriticalError};
-use crate::completion::{completion_candidates, Completion};
+use crate::completion::{
+    join_path, ArgCompleters, CommandCompleter, Completion, NameTree, Resolved,
+};
+use crate::context::{Change, HelpPages, ReplContext};
+use crate::interrupt::{
+    self, ActiveGuard, CancellationToken, InterruptPolicy, Interrupted, PromptInterrupt,
+};
+use crate::jobs::{FinishedJob, Job, JobContext, Jobs};
+use crate::line::{
+    expand_variables, is_variable_name, split_chain, split_pipeline, split_redirect,
+    strip_background, Chain, ChainExecution, Redirect,
+};
+use crate::source::{LineSource, ReplSource, RustylineSource};
 
-/// Reserved command names. These commands are always added to REPL.
-pub const RESERVED: &[(&str, &str)] = &[("help", "Show this help message"), ("quit", "Quit repl")];
+pub use crate::builtin::{Builtin, BuiltinBuilder};
 
 /// Read-eval-print loop.
 ///
 /// REPL is ment do be constructed using the builder pattern via [`Repl::builder()`].
-/// Commands are added during building and currently cannot be added/removed/modified
-/// after [`Repl`] has been built. This is because the names are used to generate Trie
-/// with all the names for fast name lookup and completion.
+/// Commands are usually added during building, but they can also be added, removed
+/// or temporarily disabled after [`Repl`] has been built, see [`Repl::add_command`],
+/// [`Repl::remove_command`] and [`Repl::set_enabled`]. Each such change regenerates
+/// the Trie with all the names used for fast name lookup and completion.
+///
+/// Commands can be organized in groups (see [`ReplBuilder::group`]), in which case they
+/// are called by giving the group name followed by the command name, e.g. `net show`.
 ///
 /// [`Repl`] can be used in two ways: one can use the [`Repl::run`] method directly to just
 /// start the evaluation loop, or [`Repl::next`] can be used to get back control between
 /// loop steps.
 pub struct Repl<'a> {
     description: String,
-    prompt: String,
+    pub(crate) prompt: String,
     text_width: usize,
+    /// Commands by their paths (group names followed by command name)
     commands: HashMap<String, Vec<Command<'a>>>,
-    trie: Rc<Trie<u8>>,
-    editor: rustyline::Editor<Completion>,
+    /// Group descriptions by group paths
+    groups: HashMap<String, String>,
+    /// Paths of disabled commands and groups
+    disabled: HashSet<String>,
+    names: Arc<NameTree>,
+    /// Help messages of the top level and of all groups, computed lazily for [`ReplContext::print_help`]
+    help_pages: Option<Rc<HelpPages>>,
+    /// Built-in commands that are enabled
+    builtins: Vec<BuiltinBuilder>,
+    pub(crate) source: ReplSource<'a>,
+    history_file: Option<PathBuf>,
     out: Box<dyn Write>,
+    /// Output of the command being executed when it is redirected to a file or a pipe,
+    /// errors and other REPL messages are still written to `out`
+    redirect: Option<Box<dyn Write>>,
+    state: Option<Box<dyn Any>>,
     predict_commands: bool,
+    suggestion_distance: usize,
+    with_autocorrect: bool,
+    catch_interrupts: bool,
+    interrupt_policy: InterruptPolicy,
+    /// Cancelled on CTRL-C while a command is executed
+    cancellation: CancellationToken,
+    /// CTRL-C has been pressed at the empty prompt, used with [`InterruptPolicy::DoublePress`]
+    pub(crate) interrupted: bool,
+    with_jobs: bool,
+    with_redirection: bool,
+    with_pipelines: bool,
+    /// Jobs executed in background
+    pub(crate) jobs: Jobs,
+    /// Job returned by the command handler being executed
+    job: Option<Job>,
+    pub(crate) with_chaining: bool,
+    with_variables: bool,
+    with_env_variables: bool,
+    variables: BTreeMap<String, String>,
+    /// Lookup of environment variables, replaced in tests
+    env_var: fn(&str) -> Option<String>,
+    /// Expansions of aliases by their names
+    aliases: BTreeMap<String, String>,
+    /// Aliases being currently expanded, used to detect recursion
+    expanding: Vec<String>,
+    stop_script_on_error: bool,
+    /// Location in the script being currently executed
+    location: Option<String>,
+    /// Files being currently executed, used to detect recursion
+    sourced: Vec<PathBuf>,
+    /// Future returned by an async command handler, with the command name
+    #[cfg(feature = "async")]
+    pub(crate) pending: Option<(String, CommandFuture<'a>)>,
+}
+
+/// Output buffer shared with the REPL, used for pipes and by [`ReplTester`](crate::testing::ReplTester).
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    pub(crate) fn take_bytes(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.borrow_mut())
+    }
+
+    pub(crate) fn take(&self) -> String {
+        String::from_utf8_lossy(&self.take_bytes()).into_owned()
+    }
 }
 
 /// State of the REPL after command execution.
@@ -44,6 +141,54 @@ pub enum LoopStatus {
     Break,
 }
 
+/// Result of executing a script, see [`Repl::run_script`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptSummary {
+    /// Number of commands that have been executed (including failed ones)
+    pub executed: usize,
+    /// Commands that failed
+    pub failures: Vec<ScriptFailure>,
+    /// Whether the script ended with a command that quits the REPL
+    pub quit: bool,
+}
+
+/// Command that failed when executing a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptFailure {
+    /// Location of the command in the form `file:line`
+    pub location: String,
+    /// The line that failed
+    pub line: String,
+    /// Error message
+    pub error: String,
+}
+
+impl ScriptSummary {
+    /// Returns `true` if no command failed.
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl std::fmt::Display for ScriptSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "executed {} commands, {} failed",
+            self.executed,
+            self.failures.len()
+        )?;
+        for failure in self.failures.iter() {
+            write!(
+                f,
+                "\n  {}: {}: {}",
+                failure.location, failure.line, failure.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
 /// Builder pattern implementation for [`Repl`].
 ///
 /// All setter methods take owned `self` so the calls can be chained, for example:
@@ -56,16 +201,36 @@ pub enum LoopStatus {
 ///     .expect("Failed to build REPL");
 /// ```
 pub struct ReplBuilder<'a> {
-    commands: Vec<(String, Command<'a>)>,
+    commands: Vec<(Vec<String>, Command<'a>)>,
+    groups: Vec<(Vec<String>, String)>,
     description: String,
     prompt: String,
     text_width: usize,
     editor_config: rustyline::config::Config,
+    line_source: Option<Box<dyn LineSource + 'a>>,
+    history_file: Option<PathBuf>,
+    history_max_size: Option<usize>,
+    history_ignore_dups: Option<bool>,
     out: Box<dyn Write>,
+    state: Option<Box<dyn Any>>,
     with_hints: bool,
     with_completion: bool,
     with_filename_completion: bool,
+    with_chaining: bool,
+    with_variables: bool,
+    with_env_variables: bool,
+    variables: BTreeMap<String, String>,
+    with_aliases: bool,
+    builtins: Vec<BuiltinBuilder>,
     predict_commands: bool,
+    suggestion_distance: usize,
+    with_autocorrect: bool,
+    catch_interrupts: bool,
+    interrupt_policy: InterruptPolicy,
+    with_jobs: bool,
+    with_redirection: bool,
+    with_pipelines: bool,
+    stop_script_on_error: bool,
 }
 
 /// Error when building REPL.
@@ -77,15 +242,42 @@ pub enum BuilderError {
     /// Given command name is not valid.
     #[error("name '{0}' cannot be parsed correctly, thus would be impossible to call")]
     InvalidName(String),
-    /// Command name is one of [`RESERVED`] names.
+    /// Command name is one of the names of enabled built-in commands, see [`ReplBuilder::builtin`].
     #[error("'{0}' is a reserved command name")]
     ReservedName(String),
+    /// History file exists but could not be loaded.
+    #[error("failed to load history from '{}'", .0.display())]
+    HistoryFile(PathBuf, #[source] ReadlineError),
+    /// Line editor could not be created.
+    #[error("failed to create line editor")]
+    Editor(#[source] ReadlineError),
+    /// CTRL-C handler could not be set, see [`ReplBuilder::catch_interrupts`].
+    #[error("failed to set CTRL-C handler: {0}")]
+    InterruptHandler(String),
+    /// Command requires a state of different type than the one set with [`ReplBuilder::with_state`].
+    #[error("command '{0}' requires REPL state of type {1}")]
+    StateType(String, &'static str),
 }
 
 pub(crate) fn split_args(line: &str) -> Result<Vec<String>, shell_words::ParseError> {
     shell_words::split(line)
 }
 
+/// Path with names separated by single spaces
+pub(crate) fn normalize_path(name: &str) -> String {
+    split_args(name).map_or_else(|_e| name.into(), |path| path.join(" "))
+}
+
+/// Command usage signature, e.g. `connect host:String [options]`
+fn signature(name: &str, cmd: &Command) -> String {
+    let options = (!cmd.options_info.is_empty()).then_some("[options]");
+    std::iter::once(name)
+        .chain(cmd.args_info.iter().map(String::as_str))
+        .chain(options)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 impl<'a> Default for ReplBuilder<'a> {
     fn default() -> Self {
         ReplBuilder {
@@ -93,15 +285,37 @@ impl<'a> Default for ReplBuilder<'a> {
             text_width: 80,
             description: Default::default(),
             commands: Default::default(),
+            groups: Default::default(),
             out: Box::new(std::io::stderr()),
+            state: None,
+            line_source: None,
+            history_file: None,
+            history_max_size: None,
+            history_ignore_dups: None,
             editor_config: rustyline::config::Config::builder()
-                .output_stream(rustyline::OutputStreamType::Stderr) // NOTE: cannot specify `out`
                 .completion_type(rustyline::CompletionType::List)
                 .build(),
             with_hints: true,
             with_completion: true,
             with_filename_completion: false,
+            with_chaining: false,
+            with_variables: false,
+            with_env_variables: false,
+            variables: Default::default(),
+            with_aliases: false,
+            builtins: Builtin::ALL
+                .iter()
+                .map(|b| BuiltinBuilder::new(*b))
+                .collect(),
             predict_commands: true,
+            suggestion_distance: 2,
+            with_autocorrect: false,
+            catch_interrupts: false,
+            interrupt_policy: InterruptPolicy::Quit,
+            with_jobs: false,
+            with_redirection: false,
+            with_pipelines: false,
+            stop_script_on_error: true,
         }
     }
 }
@@ -130,9 +344,9 @@ impl<'a> ReplBuilder<'a> {
         editor_config: rustyline::config::Config
         /// Where to print REPL output. By default [`std::io::Stderr`] is used.
         ///
-        /// Note that [`rustyline`] will always use [`std::io::Stderr`] or [`std::io::Stdout`].
-        /// These must be configured in [`ReplBuilder::editor_config`], and currently there seems to be no way
-        /// to use other output stream for [`rustyline`] (which probably also makes little sense).
+        /// Note that [`rustyline`] always prints the prompt and the edited line to [`std::io::Stdout`],
+        /// currently there seems to be no way to use other output stream for [`rustyline`]
+        /// (which probably also makes little sense).
         out: Box<dyn Write>
         /// Print command hints. Defaults to `true`.
         ///
@@ -156,6 +370,32 @@ impl<'a> ReplBuilder<'a> {
         with_completion: bool
         /// Add filename completion, besides command completion. Defaults to `false`.
         with_filename_completion: bool
+        /// Allow multiple commands on a single line. Defaults to `false`.
+        ///
+        /// Commands can be separated with `;` (execute both), `&&` (execute the second one
+        /// only if the first one succeeded) or `||` (only if the first one failed), e.g.
+        /// `connect dev1 && reset && status`. A command fails if it returns an error, but a
+        /// [`CriticalError`] always stops the REPL. Operators can be quoted, e.g. `echo ';'`.
+        with_chaining: bool
+        /// Enable variables. Defaults to `false`.
+        ///
+        /// Variables are managed with the built-in `set NAME VALUE`, `unset NAME...` and `vars`
+        /// commands. Arguments are expanded before executing commands, `$NAME` and `${NAME}`
+        /// are replaced with the variable value, the value always becomes a part of a single
+        /// argument. Variables are not expanded inside single quotes and `$` can be escaped
+        /// with a backslash, e.g. `echo '$NAME' \$NAME`. Using an undefined variable is an error.
+        with_variables: bool
+        /// Use process environment variables if a variable has not been set. Defaults to `false`.
+        ///
+        /// Only used when [`ReplBuilder::with_variables`] is enabled.
+        with_env_variables: bool
+        /// Enable aliases. Defaults to `false`.
+        ///
+        /// Aliases are managed with the built-in `alias NAME COMMAND...` and `unalias NAME...`
+        /// commands, e.g. `alias st status --verbose`. When an alias is used, it is replaced
+        /// with its command and any remaining arguments are appended, so `st dev1` becomes
+        /// `status --verbose dev1`. Aliases can use other aliases, but not recursively.
+        with_aliases: bool
         /// Execute commands when entering incomplete names. Defaults to `true`.
         ///
         /// With this option commands can be executed by entering only part of command name.
@@ -163,60 +403,525 @@ impl<'a> ReplBuilder<'a> {
         /// For example, with commands `"make"` and "`move`", entering just `mo` will resolve
         /// to `move` and the command will be executed, but entering `m` will result in an error.
         predict_commands: bool
+        /// Maximal edit distance of names suggested when a command is not found. Defaults to 2.
+        ///
+        /// When there is no command starting with the entered name, names of commands, groups
+        /// and aliases that differ from it by at most this number of characters (insertions,
+        /// deletions, substitutions or transpositions) are suggested, e.g. `stauts` will print
+        /// `Did you mean: status?`. Only the closest names are suggested. Use 0 to disable.
+        suggestion_distance: usize
+        /// Ask whether to execute the suggested command instead. Defaults to `false`.
+        ///
+        /// If there is a single suggestion for a name that has not been found (see
+        /// [`ReplBuilder::suggestion_distance`]), the user is asked to confirm it and the
+        /// corrected command is executed. It is never used when executing scripts.
+        with_autocorrect: bool
+        /// Catch CTRL-C while a command is executed. Defaults to `false`.
+        ///
+        /// Instead of killing the process, CTRL-C cancels the token available from
+        /// [`ReplContext::cancellation`] and the REPL returns to the prompt after the command
+        /// finishes, see the [`interrupt`](crate::interrupt) module. This installs a process-wide
+        /// signal handler, so it fails if the application has already set one with `ctrlc`.
+        ///
+        /// **The handler cannot be removed and stays installed after the REPL is dropped.**
+        /// CTRL-C pressed when no command is being executed by a REPL is passed to the SIGINT
+        /// disposition from before the handler was installed, by default terminating the process.
+        /// The application cannot install its own handler afterwards.
+        catch_interrupts: bool
+        /// What to do when CTRL-C is pressed at the empty prompt. Defaults to [`InterruptPolicy::Quit`].
+        ///
+        /// If [`ReplBuilder::catch_interrupts`] is enabled or the policy is not [`InterruptPolicy::Quit`],
+        /// CTRL-C on a non-empty line discards the line. Otherwise it quits the REPL, as it did
+        /// before these options were added.
+        interrupt_policy: InterruptPolicy
+        /// Enable background jobs. Defaults to `false`.
+        ///
+        /// Commands created with [`job_command!`](crate::job_command!) can then be executed
+        /// on a worker thread by adding `&` at the end, e.g. `flash firmware.bin &`. Jobs are
+        /// managed with the built-in `jobs`, `wait [ID...]` and `kill ID...` commands, see the
+        /// [`jobs`](crate::jobs) module.
+        ///
+        /// A job finishing while the user is typing is reported above the prompt, keeping
+        /// the line being edited, when the default line source reads from a terminal.
+        /// Otherwise finished jobs are reported before the next prompt.
+        /// When the REPL is dropped, jobs that are still running are cancelled and waited for
+        /// up to a second, jobs that do not stop by then are detached.
+        with_jobs: bool
+        /// Allow redirecting output of a command to a file. Defaults to `false`.
+        ///
+        /// A command followed by `> path` writes its output to the file, `>> path` appends
+        /// to it, e.g. `dump > "dump 1.txt"`. Only the output written to [`ReplContext::out`]
+        /// (or [`JobContext::out`]) is redirected, errors are still printed to the REPL output.
+        /// The operator can be quoted or escaped, e.g. `compare '>' 5`.
+        with_redirection: bool
+        /// Allow passing output of a command to the next one with `|`. Defaults to `false`.
+        ///
+        /// Output written by each command to [`ReplContext::out`] is collected and then
+        /// read by the next command from [`ReplContext::input`], e.g. `logs | grep ERROR | head 20`.
+        /// Only commands declared with `reads_input: true` (see [`command!`](crate::command!)) and
+        /// the built-in `grep [-i] [-v] PATTERN`, `head [N]`, `tail [N]` and `count` can be used
+        /// after `|`. The pipeline stops on the first failed command. The operator can be
+        /// quoted or escaped, e.g. `echo '|'`.
+        with_pipelines: bool
+        /// Stop executing a script on the first failed command. Defaults to `true`.
+        ///
+        /// Otherwise all the commands in a script will be executed and failures will be
+        /// reported in the [`ScriptSummary`], see [`Repl::run_script`].
+        stop_script_on_error: bool
     }
 
     /// Add a command with given `name`. Use along with the [`command!`] macro.
     pub fn add(mut self, name: &str, cmd: Command<'a>) -> Self {
-        self.commands.push((name.into(), cmd));
+        self.commands.push((vec![name.into()], cmd));
+        self
+    }
+
+    /// Read input lines from a custom [`LineSource`] instead of [`rustyline`].
+    ///
+    /// Any [`BufRead`] can be used, e.g. `std::io::stdin().lock()` when input is piped, see
+    /// the [`source`](crate::source) module. When a custom source is used, the options
+    /// specific to [`rustyline`] ([`ReplBuilder::editor_config`], hints, completion and
+    /// history size limits) are ignored.
+    pub fn line_source<S: LineSource + 'a>(mut self, source: S) -> Self {
+        self.line_source = Some(Box::new(source));
+        self
+    }
+
+    /// Configure a built-in command.
+    ///
+    /// Built-in commands can be renamed, given additional names, localized or disabled:
+    /// ```rust
+    /// # use easy_repl::{Repl, repl::Builtin};
+    /// let repl = Repl::builder()
+    ///     .builtin(Builtin::Help, |help| help.alias("?").description("Pokaż pomoc"))
+    ///     .builtin(Builtin::Quit, |quit| quit.name("exit"))
+    ///     .builtin(Builtin::Source, |source| source.enabled(false))
+    ///     .build()
+    ///     .expect("Failed to build REPL");
+    /// ```
+    ///
+    /// Names of enabled built-in commands cannot be used by other commands.
+    pub fn builtin<F>(mut self, builtin: Builtin, build: F) -> Self
+    where
+        F: FnOnce(BuiltinBuilder) -> BuiltinBuilder,
+    {
+        let i = self
+            .builtins
+            .iter()
+            .position(|b| b.builtin == builtin)
+            .unwrap();
+        let current = std::mem::replace(&mut self.builtins[i], BuiltinBuilder::new(builtin));
+        self.builtins[i] = build(current);
+        self
+    }
+
+    /// Set initial value of a variable, see [`ReplBuilder::with_variables`].
+    pub fn variable<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// Store history in a file. Defaults to no history file.
+    ///
+    /// History will be loaded from this file (if it exists) when building the REPL, and new
+    /// entries will be appended to it before each command is executed (so they are kept even if
+    /// the command never returns) and when the REPL exits. The file is
+    /// locked when reading/writing, so multiple REPL sessions can use the same history file.
+    /// Ignored if the [`LineSource`] has no history.
+    pub fn history_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.history_file = Some(path.into());
+        self
+    }
+
+    /// Maximum number of history entries, overrides [`ReplBuilder::editor_config`].
+    pub fn history_max_size(mut self, size: usize) -> Self {
+        self.history_max_size = Some(size);
+        self
+    }
+
+    /// Do not add an entry to history if it is the same as the previous one,
+    /// overrides [`ReplBuilder::editor_config`].
+    pub fn history_ignore_dups(mut self, ignore: bool) -> Self {
+        self.history_ignore_dups = Some(ignore);
+        self
+    }
+
+    /// Set application state shared by all commands.
+    ///
+    /// Commands can access the state by specifying `name: &mut S` as the first argument in
+    /// [`command!`] (after [`ReplContext`] if used), which will be passed to the handler
+    /// as the first parameter:
+    /// ```rust
+    /// # use easy_repl::{Repl, CommandStatus, command};
+    /// struct Counter {
+    ///     value: i32,
+    /// }
+    ///
+    /// let mut repl = Repl::builder()
+    ///     .with_state(Counter { value: 0 })
+    ///     .add("inc", command! {
+    ///         "Increment counter",
+    ///         (counter: &mut Counter, by: i32 = 1) => |counter: &mut Counter, by| {
+    ///             counter.value += by;
+    ///             Ok(CommandStatus::Done)
+    ///         }
+    ///     })
+    ///     .build()
+    ///     .expect("Failed to build REPL");
+    /// # assert_eq!(repl.state::<Counter>().unwrap().value, 0);
+    /// ```
+    /// The state is also available via [`ReplContext::state`] and [`Repl::state`].
+    /// [`ReplBuilder::build`] fails with [`BuilderError::StateType`] if any command
+    /// takes the state of a different type.
+    pub fn with_state<S: Any>(mut self, state: S) -> Self {
+        self.state = Some(Box::new(state));
+        self
+    }
+
+    /// Add a group of subcommands with given `name`.
+    ///
+    /// Commands in the group are called by giving the group name followed by the command
+    /// name. Groups can be nested. For example:
+    /// ```rust
+    /// # use easy_repl::{Repl, CommandStatus, command};
+    /// let repl = Repl::builder()
+    ///     .group("net", |g| g
+    ///         .description("Network configuration")
+    ///         .add("show", command!("Show configuration", () => || Ok(CommandStatus::Done)))
+    ///         .group("route", |g| g
+    ///             .add("add", command!("Add route", (addr: String) => |_a| Ok(CommandStatus::Done)))))
+    ///     .build()
+    ///     .expect("Failed to build REPL");
+    /// ```
+    /// will create commands `net show` and `net route add`. Use `help net` to show help for
+    /// commands in a group.
+    pub fn group<F>(mut self, name: &str, build: F) -> Self
+    where
+        F: FnOnce(GroupBuilder<'a>) -> GroupBuilder<'a>,
+    {
+        let group = build(GroupBuilder::new(vec![name.into()]));
+        self.commands.extend(group.commands);
+        self.groups.push((group.path, group.description));
+        self.groups.extend(group.groups);
         self
     }
 
     /// Finalize the configuration and return the REPL or error.
     pub fn build(self) -> Result<Repl<'a>, BuilderError> {
+        if self.catch_interrupts {
+            interrupt::install_handler().map_err(BuilderError::InterruptHandler)?;
+        }
+        let builtins: Vec<_> = self
+            .builtins
+            .into_iter()
+            .filter(|b| b.enabled)
+            .filter(|b| match b.builtin {
+                Builtin::Set | Builtin::Unset | Builtin::Vars => self.with_variables,
+                Builtin::Alias | Builtin::Unalias => self.with_aliases,
+                Builtin::Jobs | Builtin::Wait | Builtin::Kill => self.with_jobs,
+                b if b.reads_input() => self.with_pipelines,
+                _ => true,
+            })
+            .collect();
+        let mut builtin_names = HashSet::new();
+        for name in builtins.iter().flat_map(|b| b.names.iter()) {
+            check_path(std::slice::from_ref(name))?;
+            if !builtin_names.insert(name) {
+                return Err(BuilderError::DuplicateCommands(name.clone()));
+            }
+        }
+
+        let mut groups: HashMap<String, String> = HashMap::new();
+        for (path, description) in self.groups {
+            let path = check_path(&path)?;
+            if is_reserved(&builtins, &path) {
+                return Err(BuilderError::ReservedName(path));
+            }
+            // a group may be defined multiple times, keep the last description
+            let desc = groups.entry(path).or_default();
+            if !description.is_empty() {
+                *desc = description;
+            }
+        }
+
         let mut commands: HashMap<String, Vec<Command<'a>>> = HashMap::new();
-        let mut trie = TrieBuilder::new();
-        for (name, cmd) in self.commands {
-            let cmds = commands.entry(name.clone()).or_default();
-            let args = split_args(&name).map_err(|_e| BuilderError::InvalidName(name.clone()))?;
-            if args.len() != 1 || name.is_empty() {
-                return Err(BuilderError::InvalidName(name));
-            } else if RESERVED.iter().any(|(n, _)| *n == name) {
-                return Err(BuilderError::ReservedName(name));
-            } else if cmds.iter().any(|c| c.arg_types() == cmd.arg_types()) {
-                return Err(BuilderError::DuplicateCommands(name));
-            }
-            cmds.push(cmd);
-            trie.push(name);
-        }
-        for (name, _) in RESERVED.iter() {
-            trie.push(name);
-        }
-
-        let trie = Rc::new(trie.build());
-        let helper = Completion {
-            trie: trie.clone(),
-            with_hints: self.with_hints,
-            with_completion: self.with_completion,
-            filename_completer: if self.with_filename_completion {
-                Some(FilenameCompleter::new())
-            } else {
-                None
-            },
+        for (path, cmd) in self.commands {
+            insert_command(
+                &mut commands,
+                &groups,
+                &builtins,
+                self.state.as_deref(),
+                &path,
+                cmd,
+            )?;
+        }
+
+        let names = Arc::new(name_tree(
+            &commands,
+            &groups,
+            &builtins,
+            &Default::default(),
+            &HashSet::new(),
+        ));
+        let completer = CommandCompleter {
+            names: names.clone(),
+            variables: variable_names(
+                self.with_variables.then_some(&self.variables),
+                self.with_env_variables,
+            ),
+            args: arg_completers(&commands),
+            predict_commands: self.predict_commands,
+        };
+        let mut source = match self.line_source {
+            Some(mut source) => {
+                source.set_completer(completer);
+                ReplSource::Custom(source)
+            }
+            None => {
+                let helper = Completion {
+                    completer,
+                    with_hints: self.with_hints,
+                    with_completion: self.with_completion,
+                    filename_completer: if self.with_filename_completion {
+                        Some(FilenameCompleter::new())
+                    } else {
+                        None
+                    },
+                };
+                let mut editor = rustyline::Editor::with_config(self.editor_config)
+                    .map_err(BuilderError::Editor)?;
+                editor.set_helper(Some(helper));
+                let interrupt = PromptInterrupt::new();
+                editor.bind_sequence(
+                    rustyline::KeyEvent::ctrl('C'),
+                    rustyline::EventHandler::Conditional(Box::new(interrupt.clone())),
+                );
+                if let Some(size) = self.history_max_size {
+                    editor.set_max_history_size(size);
+                }
+                if let Some(ignore) = self.history_ignore_dups {
+                    editor.set_history_ignore_dups(ignore);
+                }
+                ReplSource::Rustyline(Box::new(RustylineSource { editor, interrupt }))
+            }
         };
-        let mut editor = rustyline::Editor::with_config(self.editor_config);
-        editor.set_helper(Some(helper));
+        if let (Some(path), Some(history)) = (self.history_file.as_ref(), source.history()) {
+            match history.load(path) {
+                Err(ReadlineError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(BuilderError::HistoryFile(path.clone(), err)),
+                Ok(()) => {}
+            }
+        }
+        let mut jobs = Jobs::default();
+        if let (true, ReplSource::Rustyline(source)) = (self.with_jobs, &mut source) {
+            // only available when reading from a terminal
+            if let Ok(printer) = source.editor.create_external_printer() {
+                jobs.set_printer(Box::new(printer));
+            }
+        }
 
         Ok(Repl {
             description: self.description,
             prompt: self.prompt,
             text_width: self.text_width,
             commands,
-            trie,
-            editor,
+            groups,
+            disabled: Default::default(),
+            names,
+            help_pages: None,
+            builtins,
+            source,
+            history_file: self.history_file,
             out: self.out,
+            redirect: None,
+            state: self.state,
             predict_commands: self.predict_commands,
+            suggestion_distance: self.suggestion_distance,
+            with_autocorrect: self.with_autocorrect,
+            catch_interrupts: self.catch_interrupts,
+            interrupt_policy: self.interrupt_policy,
+            cancellation: CancellationToken::new(),
+            interrupted: false,
+            with_jobs: self.with_jobs,
+            with_redirection: self.with_redirection,
+            with_pipelines: self.with_pipelines,
+            jobs,
+            job: None,
+            with_chaining: self.with_chaining,
+            with_variables: self.with_variables,
+            with_env_variables: self.with_env_variables,
+            variables: self.variables,
+            env_var: |name| std::env::var(name).ok(),
+            aliases: Default::default(),
+            expanding: vec![],
+            stop_script_on_error: self.stop_script_on_error,
+            location: None,
+            sourced: vec![],
+            #[cfg(feature = "async")]
+            pending: None,
         })
     }
+
+    /// Finalize the configuration and return a REPL that can execute async commands,
+    /// see [`AsyncRepl`](crate::AsyncRepl).
+    #[cfg(feature = "async")]
+    pub fn build_async(self) -> Result<crate::AsyncRepl<'a>, BuilderError> {
+        self.build().map(crate::AsyncRepl::new)
+    }
+}
+
+/// Validate names in command/group path and return the path as a single string
+fn check_path(path: &[String]) -> Result<String, BuilderError> {
+    let joined = path.join(" ");
+    if path.is_empty() {
+        return Err(BuilderError::InvalidName(joined));
+    }
+    for name in path {
+        let args = split_args(name).map_err(|_e| BuilderError::InvalidName(joined.clone()))?;
+        if args.len() != 1 || name.is_empty() {
+            return Err(BuilderError::InvalidName(joined));
+        }
+    }
+    Ok(joined)
+}
+
+fn is_reserved(builtins: &[BuiltinBuilder], path: &str) -> bool {
+    builtins
+        .iter()
+        .any(|b| b.names.iter().any(|name| name == path))
+}
+
+fn insert_command<'a>(
+    commands: &mut HashMap<String, Vec<Command<'a>>>,
+    groups: &HashMap<String, String>,
+    builtins: &[BuiltinBuilder],
+    state: Option<&dyn Any>,
+    path: &[String],
+    cmd: Command<'a>,
+) -> Result<(), BuilderError> {
+    let name = check_path(path)?;
+    if is_reserved(builtins, &name) {
+        return Err(BuilderError::ReservedName(name));
+    }
+    if let Some(required) = cmd.state_type {
+        if !state.is_some_and(|state| required.matches(state)) {
+            return Err(BuilderError::StateType(name, required.name()));
+        }
+    }
+    let cmds = commands.entry(name.clone()).or_default();
+    if groups.contains_key(&name) || cmds.iter().any(|c| c.arg_types() == cmd.arg_types()) {
+        if cmds.is_empty() {
+            commands.remove(&name);
+        }
+        return Err(BuilderError::DuplicateCommands(name));
+    }
+    cmds.push(cmd);
+    Ok(())
+}
+
+/// Check if neither the path nor any of its parent groups has been disabled
+fn is_enabled(disabled: &HashSet<String>, path: &str) -> bool {
+    let parents = path.match_indices(' ').map(|(i, _)| &path[..i]);
+    !parents
+        .chain(std::iter::once(path))
+        .any(|p| disabled.contains(p))
+}
+
+fn name_tree(
+    commands: &HashMap<String, Vec<Command>>,
+    groups: &HashMap<String, String>,
+    builtins: &[BuiltinBuilder],
+    aliases: &BTreeMap<String, String>,
+    disabled: &HashSet<String>,
+) -> NameTree {
+    let enabled = |path: &&String| is_enabled(disabled, path);
+    let names = commands
+        .keys()
+        .filter(enabled)
+        .map(String::as_str)
+        .chain(
+            builtins
+                .iter()
+                .flat_map(|b| b.names.iter().map(String::as_str)),
+        )
+        .chain(aliases.keys().map(String::as_str));
+    let groups = groups.keys().filter(enabled).map(String::as_str);
+    NameTree::new(names, groups)
+}
+
+/// Sorted names of variables used for completion, empty if variables are disabled
+fn variable_names(variables: Option<&BTreeMap<String, String>>, env: bool) -> Arc<Vec<String>> {
+    let mut names: Vec<_> = match variables {
+        Some(variables) => variables.keys().cloned().collect(),
+        None => return Arc::new(vec![]),
+    };
+    if env {
+        let env_names = std::env::vars_os().filter_map(|(name, _)| name.into_string().ok());
+        names.extend(env_names.filter(|name| is_variable_name(name)));
+        names.sort_unstable();
+        names.dedup();
+    }
+    Arc::new(names)
+}
+
+/// Argument completers of all the commands, see [`Command::arg_completers`]
+fn arg_completers(
+    commands: &HashMap<String, Vec<Command>>,
+) -> Arc<HashMap<String, Vec<ArgCompleters>>> {
+    let completers = commands.iter().map(|(path, overloads)| {
+        let overloads = overloads.iter().map(|cmd| cmd.arg_completers.clone());
+        (path.clone(), overloads.collect())
+    });
+    Arc::new(completers.collect())
+}
+
+/// Builder for a group of commands, see [`ReplBuilder::group`].
+pub struct GroupBuilder<'a> {
+    path: Vec<String>,
+    description: String,
+    commands: Vec<(Vec<String>, Command<'a>)>,
+    groups: Vec<(Vec<String>, String)>,
+}
+
+impl<'a> GroupBuilder<'a> {
+    fn new(path: Vec<String>) -> Self {
+        GroupBuilder {
+            path,
+            description: Default::default(),
+            commands: Default::default(),
+            groups: Default::default(),
+        }
+    }
+
+    fn subpath(&self, name: &str) -> Vec<String> {
+        let mut path = self.path.clone();
+        path.push(name.into());
+        path
+    }
+
+    /// Group description shown in [`Repl::help`]. Defaults to an empty string.
+    pub fn description<T: Into<String>>(mut self, v: T) -> Self {
+        self.description = v.into();
+        self
+    }
+
+    /// Add a command with given `name` to this group.
+    pub fn add(mut self, name: &str, cmd: Command<'a>) -> Self {
+        self.commands.push((self.subpath(name), cmd));
+        self
+    }
+
+    /// Add a nested group with given `name`, see [`ReplBuilder::group`].
+    pub fn group<F>(mut self, name: &str, build: F) -> Self
+    where
+        F: FnOnce(GroupBuilder<'a>) -> GroupBuilder<'a>,
+    {
+        let group = build(GroupBuilder::new(self.subpath(name)));
+        self.commands.extend(group.commands);
+        self.groups.push((group.path, group.description));
+        self.groups.extend(group.groups);
+        self
+    }
 }
 
 impl<'a> Repl<'a> {
@@ -225,6 +930,168 @@ impl<'a> Repl<'a> {
         ReplBuilder::default()
     }
 
+    /// Application state set with [`ReplBuilder::with_state`], [`None`] if it has not been
+    /// set or is not of type `T`.
+    pub fn state<T: Any>(&mut self) -> Option<&mut T> {
+        self.state.as_deref_mut()?.downcast_mut()
+    }
+
+    /// Value of a variable, falls back to environment variables if enabled.
+    /// Returns [`None`] if the variable is not set or variables are disabled.
+    pub fn variable(&self, name: &str) -> Option<String> {
+        if !self.with_variables {
+            return None;
+        }
+        self.variables
+            .get(name)
+            .cloned()
+            .or_else(|| self.with_env_variables.then(|| (self.env_var)(name))?)
+    }
+
+    /// Add a command after the REPL has been built.
+    ///
+    /// Command `name` can be a path to a command inside a group, e.g. `"net show"`. Any groups
+    /// on the path that do not exist yet will be created (without descriptions). Validation
+    /// is the same as in [`ReplBuilder::build`].
+    pub fn add_command(&mut self, name: &str, cmd: Command<'a>) -> Result<(), BuilderError> {
+        let path = split_args(name).map_err(|_e| BuilderError::InvalidName(name.into()))?;
+        check_path(&path)?;
+        if self.aliases.contains_key(&path[0]) {
+            return Err(BuilderError::DuplicateCommands(path[0].clone()));
+        }
+        let mut new_groups = vec![];
+        for i in 1..path.len() {
+            let group = path[..i].join(" ");
+            if is_reserved(&self.builtins, &group) {
+                return Err(BuilderError::ReservedName(group));
+            } else if self.commands.contains_key(&group) {
+                return Err(BuilderError::DuplicateCommands(group));
+            } else if !self.groups.contains_key(&group) {
+                new_groups.push(group);
+            }
+        }
+        insert_command(
+            &mut self.commands,
+            &self.groups,
+            &self.builtins,
+            self.state.as_deref(),
+            &path,
+            cmd,
+        )?;
+        for group in new_groups {
+            self.groups.insert(group, String::new());
+        }
+        self.update_names();
+        Ok(())
+    }
+
+    /// Remove all overloads of a command, or a whole group with all its commands.
+    ///
+    /// Returns `false` if there was no such command or group.
+    pub fn remove_command(&mut self, name: &str) -> bool {
+        let path = normalize_path(name);
+        let removed = if self.commands.remove(&path).is_some() {
+            true
+        } else if self.groups.remove(&path).is_some() {
+            let prefix = format!("{path} ");
+            self.commands.retain(|p, _| !p.starts_with(&prefix));
+            self.groups.retain(|p, _| !p.starts_with(&prefix));
+            self.disabled.retain(|p| !p.starts_with(&prefix));
+            true
+        } else {
+            false
+        };
+        if removed {
+            self.disabled.remove(&path);
+            self.update_names();
+        }
+        removed
+    }
+
+    /// Enable or disable a command or a whole group.
+    ///
+    /// Disabled commands are kept, but they cannot be executed, completed and are not
+    /// shown in help until enabled again. Returns `false` if there was no such command or group.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let path = normalize_path(name);
+        if !self.commands.contains_key(&path) && !self.groups.contains_key(&path) {
+            return false;
+        }
+        if enabled {
+            self.disabled.remove(&path);
+        } else {
+            self.disabled.insert(path);
+        }
+        self.update_names();
+        true
+    }
+
+    #[cfg_attr(not(feature = "async"), allow(unused_variables))]
+    fn apply_changes(&mut self, name: &str, changes: Vec<Change<'a>>) -> anyhow::Result<()> {
+        for change in changes {
+            match change {
+                Change::Add(name, cmd) => self.add_command(&name, *cmd)?,
+                Change::Remove(name) => {
+                    self.remove_command(&name);
+                }
+                Change::SetEnabled(name, enabled) => {
+                    self.set_enabled(&name, enabled);
+                }
+                Change::Job(job) => self.job = Some(job),
+                #[cfg(feature = "async")]
+                Change::Pending(future) => {
+                    if self.pending.is_some() {
+                        anyhow::bail!("async command '{name}' cannot be executed here");
+                    }
+                    self.pending = Some((name.to_string(), future));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Regenerate names used for lookup, must be called after any change to commands
+    fn update_names(&mut self) {
+        self.names = Arc::new(name_tree(
+            &self.commands,
+            &self.groups,
+            &self.builtins,
+            &self.aliases,
+            &self.disabled,
+        ));
+        self.help_pages = None;
+        self.update_completer();
+    }
+
+    /// Help messages of the top level (empty path) and of all enabled groups
+    fn help_pages(&mut self) -> Rc<HelpPages> {
+        if self.help_pages.is_none() {
+            let groups = self
+                .groups
+                .keys()
+                .filter(|group| is_enabled(&self.disabled, group));
+            let pages = std::iter::once("")
+                .chain(groups.map(String::as_str))
+                .map(|group| (group.to_string(), self.format_help(group)))
+                .collect();
+            self.help_pages = Some(Rc::new(pages));
+        }
+        self.help_pages.clone().unwrap()
+    }
+
+    /// Pass current command names, variable names and argument completers to the line source
+    pub(crate) fn update_completer(&mut self) {
+        self.source.set_completer(CommandCompleter {
+            names: self.names.clone(),
+            variables: variable_names(
+                self.with_variables.then_some(&self.variables),
+                self.with_env_variables,
+            ),
+            args: arg_completers(&self.commands),
+            predict_commands: self.predict_commands,
+        });
+    }
+
     fn format_help_entries(&self, entries: &[(String, String)]) -> String {
         if entries.is_empty() {
             return String::new();
@@ -243,7 +1110,7 @@ impl<'a> Repl<'a> {
                     .initial_indent("")
                     .subsequent_indent(&indent);
                 let line = format!("  {sig:width$}  {desc}");
-                textwrap::fill(&line, opts)
+                textwrap::fill(line.trim_end(), opts)
             })
             .fold(String::new(), |mut out, next| {
                 out.push('\n');
@@ -254,26 +1121,140 @@ impl<'a> Repl<'a> {
 
     /// Returns formatted help message.
     pub fn help(&self) -> String {
-        let mut names: Vec<_> = self.commands.keys().collect();
-        names.sort();
+        self.format_help("")
+    }
+
+    /// Returns formatted help message for commands inside a group, [`None`] if there is no such group.
+    ///
+    /// Groups are given by their full path, e.g. `"net route"`.
+    pub fn group_help(&self, group: &str) -> Option<String> {
+        self.groups
+            .get(group)
+            .filter(|_| is_enabled(&self.disabled, group))
+            .map(|_| self.format_help(group))
+    }
 
-        let signature =
-            |name: &String, args_info: &Vec<String>| format!("{} {}", name, args_info.join(" "));
-        let user: Vec<_> = self
+    /// Returns formatted help message for a single command with all its overloads,
+    /// [`None`] if there is no such command.
+    ///
+    /// Commands inside groups are given by their full path, e.g. `"net show"`.
+    pub fn command_help(&self, name: &str) -> Option<String> {
+        if let Some(builtin) = self.find_builtin(name) {
+            return Some(format!("{name}\n  {}", builtin.description));
+        }
+        if let Some(expansion) = self.aliases.get(name) {
+            return Some(format!("{name}\n  Alias for: {expansion}"));
+        }
+        let cmds = self
             .commands
+            .get(name)
+            .filter(|_| is_enabled(&self.disabled, name))?;
+        let indented = |text: &str| {
+            let opts = textwrap::Options::new(self.text_width)
+                .initial_indent("  ")
+                .subsequent_indent("  ");
+            textwrap::fill(text, opts)
+        };
+        let sections: Vec<_> = cmds
             .iter()
-            .flat_map(|(name, cmds)| {
-                cmds.iter()
-                    .map(move |cmd| (signature(name, &cmd.args_info), cmd.description.clone()))
+            .map(|cmd| {
+                let mut out = format!("{}\n{}", signature(name, cmd), indented(&cmd.description));
+                let paragraphs = cmd.long_description.split("\n\n").map(str::trim);
+                for paragraph in paragraphs.filter(|p| !p.is_empty()) {
+                    out += "\n\n";
+                    out += &indented(paragraph);
+                }
+                let describe = |info: &String| {
+                    let desc = cmd.arg_descriptions.iter().find(|(arg, _)| arg == info);
+                    (
+                        info.clone(),
+                        desc.map(|(_, d)| d.clone()).unwrap_or_default(),
+                    )
+                };
+                let args: Vec<_> = cmd.args_info.iter().map(describe).collect();
+                let options: Vec<_> = cmd.options_info.iter().map(describe).collect();
+                let examples: Vec<_> = cmd
+                    .examples
+                    .iter()
+                    .map(|e| (e.clone(), String::new()))
+                    .collect();
+                for (title, entries) in [
+                    ("Arguments", args),
+                    ("Options", options),
+                    ("Examples", examples),
+                ] {
+                    if !entries.is_empty() {
+                        out += &format!("\n\n{title}:{}", self.format_help_entries(&entries));
+                    }
+                }
+                out
+            })
+            .collect();
+        Some(sections.join("\n\n"))
+    }
+
+    fn format_help(&self, group: &str) -> String {
+        let in_group = |path: &String| {
+            let parent = path.rsplit_once(' ').map_or("", |(parent, _)| parent);
+            parent == group && is_enabled(&self.disabled, path)
+        };
+        let mut names: Vec<_> = self.commands.keys().filter(|p| in_group(p)).collect();
+        names.sort();
+        let mut groups: Vec<_> = self.groups.iter().filter(|(p, _)| in_group(p)).collect();
+        groups.sort();
+
+        // options and described arguments are listed below each command
+        let user: Vec<_> = names
+            .into_iter()
+            .flat_map(|name| self.commands[name].iter().map(move |cmd| (name, cmd)))
+            .flat_map(|(name, cmd)| {
+                let describe = |info: &String| {
+                    cmd.arg_descriptions
+                        .iter()
+                        .find(|(arg, _)| arg == info)
+                        .map(|(_, desc)| desc.clone())
+                };
+                let args = cmd
+                    .args_info
+                    .iter()
+                    .filter_map(move |arg| describe(arg).map(|desc| (format!("    {arg}"), desc)));
+                let options = cmd
+                    .options_info
+                    .iter()
+                    .map(move |opt| (format!("    {opt}"), describe(opt).unwrap_or_default()));
+                std::iter::once((signature(name, cmd), cmd.description.clone()))
+                    .chain(args)
+                    .chain(options)
             })
+            .chain(
+                groups
+                    .into_iter()
+                    .map(|(path, desc)| (format!("{path} <command>"), desc.clone())),
+            )
+            .collect();
+
+        if !group.is_empty() {
+            let msg = format!(
+                "{}\n\nAvailable commands:{}",
+                self.groups[group],
+                self.format_help_entries(&user)
+            );
+            return msg.trim().into();
+        }
+
+        let other: Vec<_> = self
+            .builtins
+            .iter()
+            .map(|b| (b.names.join(", "), b.description.clone()))
             .collect();
 
-        let other: Vec<_> = RESERVED
+        let aliases: Vec<_> = self
+            .aliases
             .iter()
-            .map(|(name, desc)| ((*name).to_string(), desc.to_string()))
+            .map(|(name, expansion)| (name.clone(), expansion.clone()))
             .collect();
 
-        let msg = format!(
+        let mut msg = format!(
             r#"
 {}
 
@@ -287,122 +1268,855 @@ Other commands:
             self.format_help_entries(&user),
             self.format_help_entries(&other)
         );
+        if !aliases.is_empty() {
+            msg = format!(
+                "{}\n\nAliases:\n{}",
+                msg.trim_end(),
+                self.format_help_entries(&aliases)
+            );
+        }
         msg.trim().into()
     }
 
-    fn handle_line(&mut self, line: &str) -> anyhow::Result<LoopStatus> {
-        // if there is any parsing error just continue to next input
-        let args = match split_args(line) {
-            Err(err) => {
-                writeln!(&mut self.out, "Error: {err}")?;
-                return Ok(LoopStatus::Continue);
-            }
-            Ok(args) => args,
-        };
-        let prefix = &args[0];
-        let mut candidates = completion_candidates(&self.trie, prefix);
-        let exact = !candidates.is_empty() && &candidates[0] == prefix;
-        let can_take_first = !candidates.is_empty() && (exact || self.predict_commands);
-        if !can_take_first {
-            writeln!(&mut self.out, "Command not found: {prefix}")?;
-            if candidates.len() > 1 || (!self.predict_commands && !exact) {
-                candidates.sort();
-                writeln!(&mut self.out, "Candidates:\n  {}", candidates.join("\n  "))?;
-            }
-            writeln!(&mut self.out, "Use 'help' to see available commands.")?;
-            Ok(LoopStatus::Continue)
+    /// Location in a script when executing it, used as a prefix for error messages
+    fn location_prefix(&self) -> String {
+        self.location
+            .as_ref()
+            .map_or_else(String::new, |location| format!("{location}: "))
+    }
+
+    pub(crate) fn write_error(&mut self, err: &dyn std::fmt::Display) -> anyhow::Result<()> {
+        let prefix = self.location_prefix();
+        writeln!(&mut self.out, "{prefix}Error: {err}")?;
+        Ok(())
+    }
+
+    /// Names similar to the last word of `path` that could not be found
+    fn suggestions(&self, path: &str) -> Vec<String> {
+        let (group, name) = path.rsplit_once(' ').unwrap_or(("", path));
+        self.names
+            .similar(group, name, self.suggestion_distance)
+            .iter()
+            .map(|name| join_path(group, name))
+            .collect()
+    }
+
+    fn write_not_found(&mut self, path: &str, mut candidates: Vec<String>) -> anyhow::Result<()> {
+        let prefix = self.location_prefix();
+        writeln!(&mut self.out, "{prefix}Command not found: {path}")?;
+        if !candidates.is_empty() {
+            candidates.sort();
+            writeln!(&mut self.out, "Candidates:\n  {}", candidates.join("\n  "))?;
         } else {
-            let name = &candidates[0];
-            let tail: Vec<_> = args[1..].iter().map(String::as_str).collect();
-            match self.handle_command(name, &tail) {
-                Ok(CommandStatus::Done) => Ok(LoopStatus::Continue),
-                Ok(CommandStatus::Quit) => Ok(LoopStatus::Break),
-                Err(err) if err.downcast_ref::<CriticalError>().is_some() => Err(err),
-                Err(err) => {
-                    // other errors are handled here
-                    writeln!(&mut self.out, "Error: {err}")?;
-                    if err.is::<ArgsError>() {
-                        // in case of ArgsError we know it could not have been a reserved command
-                        let cmds = self.commands.get_mut(name).unwrap();
-                        writeln!(&mut self.out, "Usage:")?;
-                        for cmd in cmds.iter() {
-                            writeln!(&mut self.out, "  {} {}", name, cmd.args_info.join(" "))?;
-                        }
-                    }
-                    Ok(LoopStatus::Continue)
-                }
+            let suggestions = self.suggestions(path);
+            if !suggestions.is_empty() {
+                writeln!(&mut self.out, "Did you mean: {}?", suggestions.join(" or "))?;
             }
         }
+        if let Some(help) = self.builtins.iter().find(|b| b.builtin == Builtin::Help) {
+            let help = &help.names[0];
+            writeln!(&mut self.out, "Use '{help}' to see available commands.")?;
+        }
+        Ok(())
     }
 
-    /// Run a single REPL iteration and return whether this is the last one or not.
-    pub fn next(&mut self) -> anyhow::Result<LoopStatus> {
-        match self.editor.readline(&self.prompt) {
-            Ok(line) => {
-                if !line.trim().is_empty() {
-                    self.editor.add_history_entry(line.trim());
-                    self.handle_line(&line)
-                } else {
-                    Ok(LoopStatus::Continue)
-                }
-            }
-            Err(ReadlineError::Interrupted) => {
-                writeln!(&mut self.out, "CTRL-C")?;
-                Ok(LoopStatus::Break)
+    fn handle_line(&mut self, line: &str) -> anyhow::Result<LoopStatus> {
+        Ok(self.execute_line(line)?.unwrap_or(LoopStatus::Continue))
+    }
+
+    /// Execute a single line. Errors other than [`CriticalError`] are printed and then
+    /// returned as `Ok(Err(message))`.
+    pub(crate) fn execute_line(
+        &mut self,
+        line: &str,
+    ) -> anyhow::Result<Result<LoopStatus, String>> {
+        let result = self.execute_chain(line)?;
+        self.reject_pending(result)
+    }
+
+    /// Execute commands of a line, see [`Repl::execute_line`]. If the last command is async,
+    /// its future is left in `pending`.
+    fn execute_chain(&mut self, line: &str) -> anyhow::Result<Result<LoopStatus, String>> {
+        let mut chain = match self.chain(line)? {
+            Ok(chain) => chain,
+            Err(err) => return Ok(Err(err)),
+        };
+        while let Some(command) = chain.next_command() {
+            let mut result = self.execute_command(command)?;
+            // result of an async command would be needed now
+            if !chain.is_last() {
+                result = self.reject_pending(result)?;
             }
-            Err(ReadlineError::Eof) => Ok(LoopStatus::Break),
-            // TODO: not sure if these should be propagated or handler here
+            chain.set_result(result);
+        }
+        Ok(chain.result())
+    }
+
+    /// Split the line into commands, without chaining the whole line is a single command.
+    pub(crate) fn chain<'l>(
+        &mut self,
+        line: &'l str,
+    ) -> anyhow::Result<Result<ChainExecution<'l>, String>> {
+        if !self.with_chaining {
+            return Ok(Ok(ChainExecution::new(vec![(Chain::Always, line)])));
+        }
+        match split_chain(line) {
+            Ok(commands) => Ok(Ok(ChainExecution::new(commands))),
             Err(err) => {
-                writeln!(&mut self.out, "Error: {err:?}")?;
-                Ok(LoopStatus::Continue)
+                self.write_error(&err)?;
+                Ok(Err(err))
             }
         }
     }
 
-    fn handle_command(&mut self, name: &str, args: &[&str]) -> anyhow::Result<CommandStatus> {
-        match name {
-            "help" => {
-                let help = self.help();
-                writeln!(&mut self.out, "{help}")?;
-                Ok(CommandStatus::Done)
-            }
-            "quit" => Ok(CommandStatus::Quit),
-            _ => {
-                // find_command must have returned correct name
-
-                // if all commands are not possible to call because of argument error
-                // return the last argument one as our result
-                let mut last_arg_err = None;
-                let cmds = self.commands.get_mut(name).unwrap();
-                for cmd in cmds.iter_mut() {
-                    match cmd.run(args) {
-                        Err(e) => {
-                            if !e.is::<ArgsError>() {
-                                return Err(e);
-                            } else {
-                                last_arg_err = Some(Err(e));
-                            }
-                        }
-                        other => return other,
-                    }
-                }
-                // last_arg_err should always have at least a value here
-                last_arg_err.unwrap()
+    /// Async commands can only be awaited by [`AsyncRepl`](crate::AsyncRepl), elsewhere they fail.
+    #[cfg(feature = "async")]
+    fn reject_pending(
+        &mut self,
+        result: Result<LoopStatus, String>,
+    ) -> anyhow::Result<Result<LoopStatus, String>> {
+        match self.pending.take() {
+            Some((name, _)) => {
+                let err = format!("async command '{name}' cannot be executed here");
+                self.write_error(&err)?;
+                Ok(Err(err))
             }
+            None => Ok(result),
         }
     }
 
-    /// Run the evaluation loop until [`LoopStatus::Break`] is received.
-    pub fn run(&mut self) -> anyhow::Result<()> {
-        while self.next()? == LoopStatus::Continue {}
-        Ok(())
+    #[cfg(not(feature = "async"))]
+    fn reject_pending(
+        &mut self,
+        result: Result<LoopStatus, String>,
+    ) -> anyhow::Result<Result<LoopStatus, String>> {
+        Ok(result)
     }
-}
+
+    /// Execute a single command or a pipeline, see [`Repl::execute_line`].
+    pub(crate) fn execute_command(
+        &mut self,
+        line: &str,
+    ) -> anyhow::Result<Result<LoopStatus, String>> {
+        if !self.with_pipelines {
+            return self.execute_stage(line, None, None);
+        }
+        let stages = match split_pipeline(line) {
+            Err(err) => {
+                self.write_error(&err)?;
+                return Ok(Err(err));
+            }
+            Ok(stages) => stages,
+        };
+        let (last, stages) = stages.split_last().unwrap();
+        if !stages.is_empty() && self.with_jobs && strip_background(last).is_some() {
+            let err = "pipeline cannot be executed in background".to_string();
+            self.write_error(&err)?;
+            return Ok(Err(err));
+        }
+        // output of each command becomes input of the next one
+        let mut input = None;
+        for stage in stages {
+            let pipe = SharedBuffer::default();
+            let result = self.execute_stage(stage, input.as_deref(), Some(&pipe))?;
+            // result of an async command would be needed now
+            let result = self.reject_pending(result)?;
+            if result != Ok(LoopStatus::Continue) {
+                return Ok(result);
+            }
+            input = Some(pipe.take_bytes());
+        }
+        self.execute_stage(last, input.as_deref(), None)
+    }
+
+    /// Execute a single command with output of the previous command in a pipeline,
+    /// its own output goes to `pipe` if given.
+    fn execute_stage(
+        &mut self,
+        line: &str,
+        input: Option<&[u8]>,
+        pipe: Option<&SharedBuffer>,
+    ) -> anyhow::Result<Result<LoopStatus, String>> {
+        let background = self.with_jobs.then(|| strip_background(line)).flatten();
+        let line = background.unwrap_or(line);
+        let expanded;
+        let line = if self.with_variables {
+            match expand_variables(line, |name| self.variable(name)) {
+                Err(err) => {
+                    self.write_error(&err)?;
+                    return Ok(Err(err));
+                }
+                Ok(line) => {
+                    expanded = line;
+                    &expanded
+                }
+            }
+        } else {
+            line
+        };
+        let (line, redirect) = if self.with_redirection {
+            match split_redirect(line) {
+                Err(err) => {
+                    self.write_error(&err)?;
+                    return Ok(Err(err));
+                }
+                Ok(split) => split,
+            }
+        } else {
+            (line, None)
+        };
+        let args = match split_args(line) {
+            Err(err) => {
+                self.write_error(&err)?;
+                return Ok(Err(err.to_string()));
+            }
+            Ok(args) => args,
+        };
+        if args.is_empty() {
+            return Ok(Ok(LoopStatus::Continue));
+        }
+        let mut args = args;
+        let (name, n_words) = loop {
+            match self.names.resolve(&args, self.predict_commands) {
+                Resolved::Command(name, n_words) => break (name, n_words),
+                Resolved::NotFound(path, candidates) => {
+                    if candidates.is_empty() && self.autocorrect(&mut args, &path)? {
+                        continue;
+                    }
+                    self.write_not_found(&path, candidates)?;
+                    return Ok(Err(format!("command not found: {path}")));
+                }
+                Resolved::Group(group) => {
+                    // group without a command, so show what can be used
+                    let help = self.format_help(&group);
+                    writeln!(&mut self.out, "{help}")?;
+                    return Ok(Ok(LoopStatus::Continue));
+                }
+            }
+        };
+        let can_background = |cmds: &Vec<Command>| cmds.iter().any(|cmd| cmd.background);
+        if background.is_some() && !self.commands.get(&name).is_some_and(can_background) {
+            let err = format!("command cannot be executed in background: {name}");
+            self.write_error(&err)?;
+            return Ok(Err(err));
+        }
+        if input.is_some() && !self.reads_input(&name) {
+            let err = format!("command does not read input: {name}");
+            self.write_error(&err)?;
+            return Ok(Err(err));
+        }
+        let file = match redirect.as_ref().map(Redirect::open).transpose() {
+            Err(err) => {
+                let path = &redirect.unwrap().path;
+                let err = format!("could not open '{path}': {err}");
+                self.write_error(&err)?;
+                return Ok(Err(err));
+            }
+            Ok(file) => file,
+        };
+        // a file given explicitly takes precedence over the pipe
+        let out: Option<Box<dyn Write>> = match (&file, pipe) {
+            (Some(file), _) => Some(Box::new(BufWriter::new(file.try_clone()?))),
+            (None, Some(pipe)) => Some(Box::new(pipe.clone())),
+            (None, None) => None,
+        };
+        if let Some(expansion) = self.aliases.get(&name) {
+            let line = format!("{expansion} {}", shell_words::join(&args[n_words..]));
+            return self.redirected(out, |repl| repl.expand_alias(name, &line));
+        }
+        let tail: Vec<_> = args[n_words..].iter().map(String::as_str).collect();
+        let result = self.redirected(out, |repl| {
+            let result = repl.handle_command(&name, &tail, input);
+            // job for background is started below, with the REPL output restored
+            Ok(match repl.job.take() {
+                Some(job) if result.is_ok() && background.is_none() => repl.run_job(job, input),
+                job => {
+                    repl.job = job;
+                    result
+                }
+            })
+        })?;
+        let result = match (self.job.take(), background) {
+            (Some(job), Some(line)) if result.is_ok() => self.spawn_job(job, line, file),
+            _ => result,
+        };
+        self.command_result(&name, result)
+    }
+
+    /// Execute `f` with the output of commands, i.e. [`ReplContext::out`] and [`JobContext::out`],
+    /// redirected to `out`. Nested commands, e.g. from an alias, inherit the redirection.
+    fn redirected<T>(
+        &mut self,
+        out: Option<Box<dyn Write>>,
+        f: impl FnOnce(&mut Self) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let out = match out {
+            Some(out) => out,
+            None => return f(self),
+        };
+        let saved = self.redirect.replace(out);
+        let result = f(self);
+        let mut out = std::mem::replace(&mut self.redirect, saved).unwrap();
+        let flushed = out.flush();
+        let result = result?;
+        flushed?;
+        Ok(result)
+    }
+
+    /// Output of the command being executed, see [`Repl::redirected`].
+    fn command_out(&mut self) -> &mut dyn Write {
+        self.redirect.as_mut().unwrap_or(&mut self.out)
+    }
+
+    /// Execute the job in the current thread.
+    fn run_job(&mut self, job: Job, input: Option<&[u8]>) -> anyhow::Result<CommandStatus> {
+        let _guard = self.catch_interrupt();
+        let cancellation = self.cancellation.clone();
+        job(&mut JobContext::new(
+            self.command_out(),
+            cancellation,
+            input,
+        ))
+    }
+
+    /// Start the job in background, its output goes to `file` if given.
+    fn spawn_job(
+        &mut self,
+        job: Job,
+        line: &str,
+        file: Option<File>,
+    ) -> anyhow::Result<CommandStatus> {
+        let out = file.map(|file| Box::new(BufWriter::new(file)) as Box<dyn Write + Send>);
+        let id = self.jobs.spawn(line, job, out)?;
+        writeln!(&mut self.out, "[{id}] {}", line.trim())?;
+        Ok(CommandStatus::Done)
+    }
+
+    /// Print output and status of the jobs that have finished.
+    pub(crate) fn report_jobs(&mut self) -> anyhow::Result<()> {
+        for job in self.jobs.finished() {
+            self.write_job(&job)?;
+        }
+        Ok(())
+    }
+
+    fn write_job(&mut self, job: &FinishedJob) -> anyhow::Result<()> {
+        self.out.write_all(&job.report())?;
+        Ok(())
+    }
+
+    /// Parse job IDs given to `wait` or `kill`
+    fn job_ids(&self, args: &[&str]) -> anyhow::Result<Vec<usize>> {
+        args.iter()
+            .map(|arg| match arg.trim_start_matches('%').parse() {
+                Ok(id) if self.jobs.contains(id) => Ok(id),
+                _ => Err(anyhow::anyhow!("no such job: {arg}")),
+            })
+            .collect()
+    }
+
+    /// Print the error if the command failed, critical errors are returned.
+    pub(crate) fn command_result(
+        &mut self,
+        name: &str,
+        result: anyhow::Result<CommandStatus>,
+    ) -> anyhow::Result<Result<LoopStatus, String>> {
+        match result {
+            Err(err) if err.downcast_ref::<CriticalError>().is_some() => Err(err),
+            // even if the command has finished
+            _ if self.cancellation.is_cancelled() => {
+                self.cancellation.reset();
+                writeln!(&mut self.out, "Interrupted")?;
+                Ok(Err(Interrupted.to_string()))
+            }
+            Ok(CommandStatus::Done) => Ok(Ok(LoopStatus::Continue)),
+            Ok(CommandStatus::Quit) => Ok(Ok(LoopStatus::Break)),
+            Err(err) if err.is::<Interrupted>() => {
+                writeln!(&mut self.out, "Interrupted")?;
+                Ok(Err(err.to_string()))
+            }
+            Err(err) => {
+                // other errors are handled here
+                self.write_error(&err)?;
+                // reserved commands have no usage information
+                if let (true, Some(cmds)) = (err.is::<ArgsError>(), self.commands.get(name)) {
+                    writeln!(&mut self.out, "Usage:")?;
+                    for cmd in cmds.iter() {
+                        writeln!(&mut self.out, "  {}", signature(name, cmd))?;
+                        for opt in cmd.options_info.iter() {
+                            writeln!(&mut self.out, "      {opt}")?;
+                        }
+                    }
+                }
+                Ok(Err(err.to_string()))
+            }
+        }
+    }
+
+    /// If there is a single suggestion for the name that has not been found, ask the user
+    /// to confirm it and replace the name in `args`. Returns `true` if the name was replaced.
+    fn autocorrect(&mut self, args: &mut [String], path: &str) -> anyhow::Result<bool> {
+        if !self.with_autocorrect || self.location.is_some() {
+            return Ok(false);
+        }
+        let suggestion = match self.suggestions(path).as_slice() {
+            [suggestion] => suggestion.clone(),
+            _ => return Ok(false),
+        };
+        let answer = match self
+            .source
+            .readline(&format!("Did you mean: {suggestion}? [y/N] "))
+        {
+            Ok(answer) => answer,
+            Err(ReadlineError::Eof | ReadlineError::Interrupted) => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Ok(false);
+        }
+        // each word of the path corresponds to a single argument
+        let index = path.split(' ').count() - 1;
+        args[index] = suggestion.rsplit(' ').next().unwrap().to_string();
+        Ok(true)
+    }
+
+    /// Check that alias name is a single word and does not conflict with commands
+    fn check_alias_name(&self, name: &str) -> anyhow::Result<()> {
+        let words = split_args(name)?;
+        if words.len() != 1 || words[0] != name || name.contains(char::is_whitespace) {
+            Err(anyhow::anyhow!("invalid alias name: '{name}'"))
+        } else if is_reserved(&self.builtins, name)
+            || self.commands.contains_key(name)
+            || self.groups.contains_key(name)
+        {
+            Err(anyhow::anyhow!(
+                "alias name conflicts with a command: '{name}'"
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Execute the line resulting from expansion of an alias
+    fn expand_alias(
+        &mut self,
+        alias: String,
+        line: &str,
+    ) -> anyhow::Result<Result<LoopStatus, String>> {
+        if self.expanding.contains(&alias) {
+            let chain: Vec<_> = self.expanding.iter().chain([&alias]).cloned().collect();
+            let err = format!("alias recursion: {}", chain.join(" -> "));
+            self.write_error(&err)?;
+            return Ok(Err(err));
+        }
+        self.expanding.push(alias);
+        let result = self.execute_chain(line);
+        self.expanding.pop();
+        result
+    }
+
+    /// Run a single REPL iteration and return whether this is the last one or not.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> anyhow::Result<LoopStatus> {
+        self.report_jobs()?;
+        self.jobs.set_reading(true);
+        let line = self.source.readline(&self.prompt);
+        self.jobs.set_reading(false);
+        match line {
+            Ok(line) => {
+                self.interrupted = false;
+                if !line.trim().is_empty() {
+                    self.source.add_history_entry(line.trim());
+                    // before executing, so that the entry is kept if the command never returns
+                    self.save_history()?;
+                    self.handle_line(&line)
+                } else {
+                    Ok(LoopStatus::Continue)
+                }
+            }
+            Err(err) => self.readline_error(err),
+        }
+    }
+
+    /// Whether CTRL-C on a non-empty line only discards it, see [`ReplBuilder::interrupt_policy`].
+    fn discards_line(&self) -> bool {
+        self.catch_interrupts || self.interrupt_policy != InterruptPolicy::Quit
+    }
+
+    /// Handle an error returned when reading the next line.
+    pub(crate) fn readline_error(&mut self, err: ReadlineError) -> anyhow::Result<LoopStatus> {
+        match err {
+            // the line is discarded, unless CTRL-C has been left to quit as it always used to
+            ReadlineError::Interrupted
+                if self.discards_line() && !self.source.interrupted_empty() =>
+            {
+                self.interrupted = false;
+                Ok(LoopStatus::Continue)
+            }
+            ReadlineError::Interrupted => match self.interrupt_policy {
+                InterruptPolicy::Clear => Ok(LoopStatus::Continue),
+                InterruptPolicy::DoublePress if !self.interrupted => {
+                    self.interrupted = true;
+                    writeln!(&mut self.out, "Press CTRL-C again to quit")?;
+                    Ok(LoopStatus::Continue)
+                }
+                InterruptPolicy::Quit | InterruptPolicy::DoublePress => {
+                    writeln!(&mut self.out, "CTRL-C")?;
+                    Ok(LoopStatus::Break)
+                }
+            },
+            ReadlineError::Eof => Ok(LoopStatus::Break),
+            // TODO: not sure if these should be propagated or handler here
+            err => {
+                writeln!(&mut self.out, "Error: {err:?}")?;
+                Ok(LoopStatus::Continue)
+            }
+        }
+    }
+
+    /// Route CTRL-C to the cancellation token until the guard is dropped.
+    pub(crate) fn catch_interrupt(&self) -> Option<ActiveGuard> {
+        self.catch_interrupts
+            .then(|| interrupt::activate(&self.cancellation))
+    }
+
+    fn handle_command(
+        &mut self,
+        name: &str,
+        args: &[&str],
+        input: Option<&[u8]>,
+    ) -> anyhow::Result<CommandStatus> {
+        let _guard = self.catch_interrupt();
+        if let Some(builtin) = self.find_builtin(name) {
+            return self.handle_builtin(builtin.builtin, args, input);
+        }
+        // find_command must have returned correct name
+
+        // if all commands are not possible to call because of argument error
+        // return the last argument one as our result
+        let mut result = None;
+        let mut changes = vec![];
+        let help = self.help_pages();
+        let out = self.redirect.as_mut().unwrap_or(&mut self.out);
+        let cmds = self.commands.get_mut(name).unwrap();
+        for cmd in cmds.iter_mut() {
+            // each overload gets a new context as handlers may take the state out of it
+            let mut ctx = ReplContext::new(
+                &mut self.prompt,
+                &mut *out,
+                self.source.history(),
+                self.state.as_deref_mut(),
+                self.cancellation.clone(),
+                input,
+                Some(&help),
+            );
+            match cmd.run_in(&mut ctx, args) {
+                Err(e) if e.is::<ArgsError>() => result = Some(Err(e)),
+                other => {
+                    result = Some(other);
+                    changes = ctx.into_changes();
+                    break;
+                }
+            }
+        }
+        // commands can only be modified after the handler is done
+        self.apply_changes(name, changes)?;
+        // result should always have a value here
+        result.unwrap()
+    }
+
+    fn find_builtin(&self, name: &str) -> Option<&BuiltinBuilder> {
+        self.builtins
+            .iter()
+            .find(|b| b.names.iter().any(|n| n == name))
+    }
+
+    /// Whether the command can be used after `|`, aliases cannot.
+    fn reads_input(&self, name: &str) -> bool {
+        match self.find_builtin(name) {
+            Some(builtin) => builtin.builtin.reads_input(),
+            None => self
+                .commands
+                .get(name)
+                .is_some_and(|cmds| cmds.iter().any(|cmd| cmd.reads_input)),
+        }
+    }
+
+    fn handle_builtin(
+        &mut self,
+        builtin: Builtin,
+        args: &[&str],
+        input: Option<&[u8]>,
+    ) -> anyhow::Result<CommandStatus> {
+        match builtin {
+            Builtin::Help => {
+                match self.names.resolve(args, self.predict_commands) {
+                    Resolved::Group(group) => {
+                        let help = self.format_help(&group);
+                        writeln!(self.command_out(), "{help}")?;
+                    }
+                    Resolved::NotFound(path, candidates) => {
+                        self.write_not_found(&path, candidates)?
+                    }
+                    Resolved::Command(path, _) => {
+                        // must exist as it has been resolved
+                        let help = self.command_help(&path).unwrap();
+                        writeln!(self.command_out(), "{help}")?;
+                    }
+                }
+                Ok(CommandStatus::Done)
+            }
+            Builtin::Quit => Ok(CommandStatus::Quit),
+            Builtin::Set => {
+                if args.len() != 2 {
+                    let (got, expected) = (args.len(), 2);
+                    return Err(ArgsError::WrongNumberOfArguments { got, expected }.into());
+                }
+                if !is_variable_name(args[0]) {
+                    return Err(anyhow::anyhow!("invalid variable name: '{}'", args[0]));
+                }
+                self.variables.insert(args[0].into(), args[1].into());
+                self.update_completer();
+                Ok(CommandStatus::Done)
+            }
+            Builtin::Unset => {
+                if args.is_empty() {
+                    let (got, min) = (args.len(), 1);
+                    return Err(ArgsError::TooFewArguments { got, min }.into());
+                }
+                for name in args {
+                    self.variables.remove(*name);
+                }
+                self.update_completer();
+                Ok(CommandStatus::Done)
+            }
+            Builtin::Alias => {
+                match args {
+                    [] => {
+                        let out = self.redirect.as_mut().unwrap_or(&mut self.out);
+                        for (name, expansion) in self.aliases.iter() {
+                            let expansion = shell_words::quote(expansion);
+                            writeln!(out, "alias {name} {expansion}")?;
+                        }
+                    }
+                    [name] => {
+                        let expansion = self
+                            .aliases
+                            .get(*name)
+                            .ok_or_else(|| anyhow::anyhow!("alias not found: {name}"))?;
+                        let expansion = shell_words::quote(expansion);
+                        let out = self.redirect.as_mut().unwrap_or(&mut self.out);
+                        writeln!(out, "alias {name} {expansion}")?;
+                    }
+                    [name, command @ ..] => {
+                        self.check_alias_name(name)?;
+                        // a single argument is used as is, e.g. `alias st "status --verbose"`
+                        let expansion = match command {
+                            [command] => command.to_string(),
+                            command => shell_words::join(command),
+                        };
+                        self.aliases.insert(name.to_string(), expansion);
+                        self.update_names();
+                    }
+                }
+                Ok(CommandStatus::Done)
+            }
+            Builtin::Unalias => {
+                if args.is_empty() {
+                    let (got, min) = (args.len(), 1);
+                    return Err(ArgsError::TooFewArguments { got, min }.into());
+                }
+                let missing: Vec<_> = args
+                    .iter()
+                    .filter(|name| self.aliases.remove(**name).is_none())
+                    .collect();
+                self.update_names();
+                if missing.is_empty() {
+                    Ok(CommandStatus::Done)
+                } else {
+                    let missing: Vec<_> = missing.iter().map(|name| name.to_string()).collect();
+                    Err(anyhow::anyhow!("alias not found: {}", missing.join(", ")))
+                }
+            }
+            Builtin::Vars => {
+                if !args.is_empty() {
+                    let (got, expected) = (args.len(), 0);
+                    return Err(ArgsError::WrongNumberOfArguments { got, expected }.into());
+                }
+                let out = self.redirect.as_mut().unwrap_or(&mut self.out);
+                for (name, value) in self.variables.iter() {
+                    writeln!(out, "{name}={value}")?;
+                }
+                Ok(CommandStatus::Done)
+            }
+            Builtin::Jobs => {
+                if !args.is_empty() {
+                    let (got, expected) = (args.len(), 0);
+                    return Err(ArgsError::WrongNumberOfArguments { got, expected }.into());
+                }
+                let out = self.redirect.as_mut().unwrap_or(&mut self.out);
+                for (id, line, running) in self.jobs.list() {
+                    if running {
+                        writeln!(out, "[{id}] Running  {line}")?;
+                    }
+                }
+                self.report_jobs()?;
+                Ok(CommandStatus::Done)
+            }
+            Builtin::Wait => {
+                let ids = match args {
+                    [] => self.jobs.list().iter().map(|(id, _, _)| *id).collect(),
+                    args => self.job_ids(args)?,
+                };
+                let mut failed = vec![];
+                for id in ids {
+                    let job = self.jobs.wait(id, &self.cancellation)?;
+                    self.write_job(&job)?;
+                    if job.result.is_err() {
+                        failed.push(format!("[{id}]"));
+                    }
+                }
+                if !failed.is_empty() {
+                    anyhow::bail!("job failed: {}", failed.join(" "));
+                }
+                Ok(CommandStatus::Done)
+            }
+            Builtin::Kill => {
+                if args.is_empty() {
+                    let (got, min) = (args.len(), 1);
+                    return Err(ArgsError::TooFewArguments { got, min }.into());
+                }
+                for id in self.job_ids(args)? {
+                    self.jobs.kill(id);
+                }
+                Ok(CommandStatus::Done)
+            }
+            Builtin::Grep | Builtin::Head | Builtin::Tail | Builtin::Count => {
+                builtin::filter(builtin, args, input, self.command_out())?;
+                Ok(CommandStatus::Done)
+            }
+            Builtin::Source => {
+                if args.len() != 1 {
+                    let (got, expected) = (args.len(), 1);
+                    return Err(ArgsError::WrongNumberOfArguments { got, expected }.into());
+                }
+                let summary = self.run_file(args[0])?;
+                if !summary.is_success() {
+                    Err(anyhow::anyhow!("{}: {summary}", args[0]))
+                } else if summary.quit {
+                    Ok(CommandStatus::Quit)
+                } else {
+                    Ok(CommandStatus::Done)
+                }
+            }
+        }
+    }
+
+    /// Execute commands read from `reader`, line by line.
+    ///
+    /// Empty lines and lines starting with `#` are skipped. Errors are printed with the location
+    /// of the command, in the form `<script>:line`. Depending on [`ReplBuilder::stop_script_on_error`]
+    /// execution stops on the first error, or all the commands are executed. Execution also stops
+    /// on a command that quits the REPL. Failures are returned in [`ScriptSummary`], only
+    /// [`CriticalError`]s and errors when reading input are returned as [`Err`].
+    pub fn run_script<R: BufRead>(&mut self, reader: R) -> anyhow::Result<ScriptSummary> {
+        self.run_script_named("<script>", reader)
+    }
+
+    /// Execute commands from a file, see [`Repl::run_script`].
+    ///
+    /// This is also available in the REPL as the `source <file>` command.
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<ScriptSummary> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|err| anyhow::anyhow!("could not open '{}': {err}", path.display()))?;
+        let canonical = path.canonicalize()?;
+        if self.sourced.contains(&canonical) {
+            anyhow::bail!("recursive execution of '{}'", path.display());
+        }
+        self.sourced.push(canonical);
+        let summary =
+            self.run_script_named(&path.display().to_string(), std::io::BufReader::new(file));
+        self.sourced.pop();
+        summary
+    }
+
+    fn run_script_named<R: BufRead>(
+        &mut self,
+        name: &str,
+        reader: R,
+    ) -> anyhow::Result<ScriptSummary> {
+        let outer = self.location.take();
+        let mut summary = ScriptSummary::default();
+        let run = || {
+            for (i, line) in reader.lines().enumerate() {
+                let line = line?;
+                let trimmed = line.trim();
+                if trimmed.is_empty() || trimmed.starts_with('#') {
+                    continue;
+                }
+                let location = format!("{name}:{}", i + 1);
+                self.location = Some(location.clone());
+                summary.executed += 1;
+                match self.execute_line(&line)? {
+                    Ok(LoopStatus::Continue) => {}
+                    Ok(LoopStatus::Break) => {
+                        summary.quit = true;
+                        break;
+                    }
+                    Err(error) => {
+                        summary.failures.push(ScriptFailure {
+                            location,
+                            line: trimmed.to_string(),
+                            error,
+                        });
+                        if self.stop_script_on_error {
+                            break;
+                        }
+                    }
+                }
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+        let result = run();
+        self.location = outer;
+        result.map(|_| summary)
+    }
+
+    /// Append new history entries to the history file, if there is one.
+    pub(crate) fn save_history(&mut self) -> anyhow::Result<()> {
+        if let (Some(path), Some(history)) = (self.history_file.as_ref(), self.source.history()) {
+            if let Err(err) = history.append(path) {
+                let path = path.display();
+                writeln!(
+                    &mut self.out,
+                    "Error: failed to save history to '{path}': {err}"
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the evaluation loop until [`LoopStatus::Break`] is received.
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        let result = loop {
+            match self.next() {
+                Ok(LoopStatus::Continue) => {}
+                Ok(LoopStatus::Break) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
+        // history could also have been modified by commands
+        self.save_history()?;
+        result
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::command;
+    use crate::testing::fixtures::{echo, fail, Calls};
 
     #[test]
     fn builder_duplicate() {
@@ -420,7 +2134,7 @@ mod tests {
             .add("name_x", command!("", (a: String) => |_| Ok(CommandStatus::Done)))
             .add("name_x", command!("", (b: i32) => |_| Ok(CommandStatus::Done)))
             .build();
-        assert!(matches!(result, Ok(_)));
+        assert!(result.is_ok());
     }
 
     #[test]
@@ -454,6 +2168,48 @@ mod tests {
         assert!(matches!(result, Err(BuilderError::ReservedName(_))));
     }
 
+    #[test]
+    fn builtins_config() {
+        let build = || {
+            Repl::builder()
+                .builtin(Builtin::Help, |b| b.alias("?").description("Pomoc"))
+                .builtin(Builtin::Quit, |b| b.name("exit"))
+                .builtin(Builtin::Source, |b| b.enabled(false))
+        };
+        #[rustfmt::skip]
+        let mut repl = build()
+            .add("quit", command!("", () => || Ok(CommandStatus::Done)))
+            .add("source", command!("", () => || Ok(CommandStatus::Done)))
+            .build()
+            .unwrap();
+        assert_eq!(repl.handle_line("quit").unwrap(), LoopStatus::Continue);
+        assert_eq!(repl.handle_line("exit").unwrap(), LoopStatus::Break);
+        assert_eq!(repl.handle_line("?").unwrap(), LoopStatus::Continue);
+        assert_eq!(repl.command_help("?").unwrap(), "?\n  Pomoc");
+        assert!(repl
+            .help()
+            .ends_with("Other commands:\n\n  help, ?  Pomoc\n  exit     Quit repl"));
+
+        let result = build()
+            .add("exit", command!("", () => || Ok(CommandStatus::Done)))
+            .build();
+        assert!(matches!(result, Err(BuilderError::ReservedName(_))));
+        let result = build().builtin(Builtin::Quit, |b| b.alias("?")).build();
+        assert!(matches!(result, Err(BuilderError::DuplicateCommands(_))));
+        let result = build().builtin(Builtin::Quit, |b| b.name("a b")).build();
+        assert!(matches!(result, Err(BuilderError::InvalidName(_))));
+
+        // kiosk mode without a way to quit
+        let mut repl = Repl::builder()
+            .builtin(Builtin::Quit, |b| b.enabled(false))
+            .build()
+            .unwrap();
+        assert_eq!(
+            repl.execute_line("quit").unwrap().unwrap_err(),
+            "command not found: quit"
+        );
+    }
+
     #[test]
     fn repl_quits() {
         let mut repl = Repl::builder()
@@ -463,7 +2219,7 @@ mod tests {
             )
             .build()
             .unwrap();
-        assert_eq!(repl.handle_line("quit".into()).unwrap(), LoopStatus::Break);
+        assert_eq!(repl.handle_line("quit").unwrap(), LoopStatus::Break);
         let mut repl = Repl::builder()
             .add(
                 "foo",
@@ -471,6 +2227,948 @@ mod tests {
             )
             .build()
             .unwrap();
-        assert_eq!(repl.handle_line("foo".into()).unwrap(), LoopStatus::Break);
+        assert_eq!(repl.handle_line("foo").unwrap(), LoopStatus::Break);
+    }
+
+    #[test]
+    fn help_optional_args() {
+        let repl = Repl::builder()
+            .add(
+                "foo",
+                command!("description", (a: i32, b: i32 = 1) => |_a, _b| Ok(CommandStatus::Done)),
+            )
+            .build()
+            .unwrap();
+        assert!(repl.help().contains("foo a:i32 [b:i32=1]  description"));
+    }
+
+    #[test]
+    fn help_options() {
+        #[rustfmt::skip]
+        let repl = Repl::builder()
+            .add("foo", command!("description", (a: i32, --verbose|-v, --level: u8) => |_a, _v, _l| Ok(CommandStatus::Done)))
+            .add("bar", command!("other", () => || Ok(CommandStatus::Done)))
+            .build()
+            .unwrap();
+        let help = repl.help();
+        let expected = [
+            "  bar                  other",
+            "  foo a:i32 [options]  description",
+            "      -v, --verbose",
+            "      --level <u8>",
+        ];
+        assert!(help.contains(&expected.join("\n")), "{}", help);
+    }
+
+    #[test]
+    fn help_arg_descriptions() {
+        let mut cmd = command!("description", (a: i32, b: i32, --verbose) => |_a, _b, _v| Ok(CommandStatus::Done));
+        cmd.arg_descriptions = vec![
+            ("b:i32".into(), "Second".into()),
+            ("--verbose".into(), "More output".into()),
+        ];
+        let repl = Repl::builder().add("foo", cmd).build().unwrap();
+        let help = repl.help();
+        let expected = [
+            "  foo a:i32 b:i32 [options]  description",
+            "      b:i32                  Second",
+            "      --verbose              More output",
+        ];
+        assert!(help.contains(&expected.join("\n")), "{}", help);
+    }
+
+    #[test]
+    fn builder_groups() {
+        #[rustfmt::skip]
+        let result = Repl::builder()
+            .add("net", command!("", () => || Ok(CommandStatus::Done)))
+            .group("net", |g| g.add("show", command!("", () => || Ok(CommandStatus::Done))))
+            .build();
+        assert!(matches!(result, Err(BuilderError::DuplicateCommands(_))));
+        #[rustfmt::skip]
+        let result = Repl::builder()
+            .group("help", |g| g.add("show", command!("", () => || Ok(CommandStatus::Done))))
+            .build();
+        assert!(matches!(result, Err(BuilderError::ReservedName(_))));
+        #[rustfmt::skip]
+        let result = Repl::builder()
+            .group("net", |g| g.add("a b", command!("", () => || Ok(CommandStatus::Done))))
+            .build();
+        assert!(matches!(result, Err(BuilderError::InvalidName(_))));
+        #[rustfmt::skip]
+        let result = Repl::builder()
+            .group("net", |g| g.add("help", command!("", () => || Ok(CommandStatus::Done))))
+            .build();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn group_commands() {
+        let calls = Calls::default();
+        #[rustfmt::skip]
+        let mut repl = Repl::builder()
+            .add("show", calls.command("show"))
+            .group("net", |g| g
+                .add("show", calls.command("net show"))
+                .group("route", |g| g
+                    .add("add", calls.command("net route add"))
+                    .add("delete", calls.command("net route delete"))))
+            .build()
+            .unwrap();
+        repl.handle_line("show").unwrap();
+        repl.handle_line("net show").unwrap();
+        repl.handle_line("n r a 1.2.3.4").unwrap();
+        repl.handle_line("net route del").unwrap();
+        repl.handle_line("net route").unwrap();
+        repl.handle_line("net route show").unwrap();
+        assert_eq!(
+            calls.take(),
+            [
+                "show",
+                "net show",
+                "net route add 1.2.3.4",
+                "net route delete"
+            ]
+        );
+    }
+
+    #[test]
+    fn help_groups() {
+        #[rustfmt::skip]
+        let repl = Repl::builder()
+            .add("foo", command!("other", () => || Ok(CommandStatus::Done)))
+            .group("net", |g| g
+                .description("Network")
+                .add("show", command!("Show network", (a: i32) => |_a| Ok(CommandStatus::Done)))
+                .group("route", |g| g.description("Routing")))
+            .build()
+            .unwrap();
+        let help = repl.help();
+        let expected = ["  foo            other", "  net <command>  Network"];
+        assert!(help.contains(&expected.join("\n")), "{}", help);
+        assert!(!help.contains("show"), "{}", help);
+
+        let help = repl.group_help("net").unwrap();
+        let expected = [
+            "Network",
+            "",
+            "Available commands:",
+            "  net show a:i32       Show network",
+            "  net route <command>  Routing",
+        ];
+        assert_eq!(help, expected.join("\n"));
+        assert!(repl.group_help("foo").is_none());
+    }
+
+    #[test]
+    fn runtime_add_remove() {
+        let calls = Calls::default();
+        let mut repl = Repl::builder()
+            .add("connect", calls.command("connect"))
+            .build()
+            .unwrap();
+        assert!(!repl.help().contains("dev"));
+        let mut reset = calls.command("dev reset");
+        reset.description = "Reset".into();
+        repl.add_command("dev reset", reset).unwrap();
+        repl.add_command("status", calls.command("status")).unwrap();
+        assert!(repl.help().contains("dev <command>"));
+        assert!(repl.group_help("dev").unwrap().contains("dev reset  Reset"));
+        repl.handle_line("dev reset").unwrap();
+        repl.handle_line("st").unwrap();
+        assert!(repl.remove_command("dev"));
+        assert!(!repl.remove_command("dev"));
+        assert!(repl.group_help("dev").is_none());
+        repl.handle_line("dev reset").unwrap();
+        assert_eq!(calls.take(), ["dev reset", "status"]);
+    }
+
+    #[test]
+    fn runtime_add_invalid() {
+        let mut repl = Repl::builder()
+            .add("foo", command!("", () => || Ok(CommandStatus::Done)))
+            .build()
+            .unwrap();
+        let mut add = |name| repl.add_command(name, command!("", () => || Ok(CommandStatus::Done)));
+        assert!(matches!(
+            add("foo"),
+            Err(BuilderError::DuplicateCommands(_))
+        ));
+        assert!(matches!(
+            add("foo bar"),
+            Err(BuilderError::DuplicateCommands(_))
+        ));
+        assert!(matches!(add("help"), Err(BuilderError::ReservedName(_))));
+        assert!(matches!(
+            add("quit now"),
+            Err(BuilderError::ReservedName(_))
+        ));
+        assert!(matches!(add(""), Err(BuilderError::InvalidName(_))));
+        assert!(add("bar baz").is_ok());
+        assert!(matches!(
+            add("bar"),
+            Err(BuilderError::DuplicateCommands(_))
+        ));
+    }
+
+    #[test]
+    fn runtime_set_enabled() {
+        let calls = Calls::default();
+        #[rustfmt::skip]
+        let mut repl = Repl::builder()
+            .add("foo", calls.command("foo"))
+            .group("net", |g| g.add("show", command!("", () => || Ok(CommandStatus::Done))))
+            .build()
+            .unwrap();
+        assert!(repl.set_enabled("foo", false));
+        assert!(repl.set_enabled("net", false));
+        assert!(!repl.set_enabled("bar", false));
+        repl.handle_line("foo").unwrap();
+        let help = repl.help();
+        assert!(!help.contains("foo") && !help.contains("net"), "{}", help);
+        assert!(repl.group_help("net").is_none());
+        assert!(repl.set_enabled("foo", true));
+        repl.handle_line("foo").unwrap();
+        assert!(repl.help().contains("foo"));
+        assert_eq!(calls.take(), ["foo"]);
+    }
+
+    #[test]
+    fn context_changes() {
+        #[rustfmt::skip]
+        let mut repl = Repl::builder()
+            .with_state(0_usize)
+            .add("use", command!("", (ctx: &mut ReplContext, name: String) => |ctx: &mut ReplContext, name: String| {
+                ctx.set_prompt(format!("{name}> "));
+                *ctx.state::<usize>().unwrap() += 1;
+                ctx.add_command(&format!("{name} reset"), command!("", () => || Ok(CommandStatus::Done)));
+                ctx.set_enabled("use", false);
+                Ok(CommandStatus::Done)
+            }))
+            .build()
+            .unwrap();
+        repl.handle_line("use dev").unwrap();
+        assert_eq!(repl.prompt, "dev> ");
+        assert_eq!(repl.state.as_ref().unwrap().downcast_ref(), Some(&1_usize));
+        assert!(repl.group_help("dev").unwrap().contains("dev reset"));
+        assert!(!repl.help().contains("use"));
+    }
+
+    #[test]
+    fn context_print_help() {
+        #[rustfmt::skip]
+        let builder = Repl::builder()
+            .description("Devices")
+            .group("dev", |g| g.description("Device commands")
+                .add("reset", command!("Reset the device", () => || Ok(CommandStatus::Done))))
+            .add("show", command!("", (ctx: &mut ReplContext, group: String) => |ctx: &mut ReplContext, group: String| {
+                ctx.print_help(&group)?;
+                writeln!(ctx.out(), "after help")?;
+                Ok(CommandStatus::Done)
+            }));
+        let mut tester = crate::testing::ReplTester::new(builder).unwrap();
+        let out = &tester.line("show dev").output;
+        assert!(out.starts_with("Device commands"));
+        assert!(
+            out.ends_with("reset  Reset the device\nafter help\n"),
+            "{out}"
+        );
+        assert!(tester.line("show ''").output.starts_with("Devices"));
+        let result = tester.line("show foo");
+        assert_eq!(result.error.as_deref(), Some("Group not found: foo"));
+
+        let mut cmd = command!("", (ctx: &mut ReplContext) => |ctx: &mut ReplContext| {
+            ctx.print_help("")?;
+            Ok(CommandStatus::Done)
+        });
+        let err = cmd.run(&[]).unwrap_err();
+        assert_eq!(err.to_string(), "help is not available outside of a REPL");
+    }
+
+    #[test]
+    fn context_invalid_changes() {
+        #[rustfmt::skip]
+        let mut repl = Repl::builder()
+            .add("add", command!("", (ctx: &mut ReplContext) => |ctx: &mut ReplContext| {
+                ctx.add_command("add", command!("", () => || Ok(CommandStatus::Done)));
+                Ok(CommandStatus::Done)
+            }))
+            .build()
+            .unwrap();
+        let result = repl.handle_command("add", &[], None);
+        assert!(result.unwrap_err().is::<BuilderError>());
+    }
+
+    #[test]
+    fn command_state() {
+        #[rustfmt::skip]
+        let mut repl = Repl::builder()
+            .with_state(vec![0_i32])
+            .add("push", command!("", (v: &mut Vec<i32>, x: i32) => |v: &mut Vec<i32>, x| {
+                v.push(x);
+                Ok(CommandStatus::Done)
+            }))
+            .add("push", command!("", (v: &mut Vec<i32>, x: i32, y: i32) => |v: &mut Vec<i32>, x, y| {
+                v.extend([x, y]);
+                Ok(CommandStatus::Done)
+            }))
+            .add("prompt", command!("", (ctx: &mut ReplContext, v: &mut Vec<i32>) => |ctx: &mut ReplContext, v: &mut Vec<i32>| {
+                ctx.set_prompt(format!("{}> ", v.len()));
+                Ok(CommandStatus::Done)
+            }))
+            .build()
+            .unwrap();
+        repl.handle_line("push 1").unwrap();
+        repl.handle_line("push 2 3").unwrap();
+        repl.handle_line("prompt").unwrap();
+        assert_eq!(repl.state::<Vec<i32>>().unwrap(), &[0, 1, 2, 3]);
+        assert_eq!(repl.prompt, "4> ");
+        let wrong = command!("", (_s: &mut String) => |_s: &mut String| Ok(CommandStatus::Done));
+        let result = repl.add_command("wrong", wrong);
+        assert!(matches!(result, Err(BuilderError::StateType(_, _))));
+        let result = Repl::builder()
+            .add(
+                "push",
+                command!("", (_v: &mut Vec<i32>) => |_v: &mut Vec<i32>| Ok(CommandStatus::Done)),
+            )
+            .build();
+        assert!(matches!(result, Err(BuilderError::StateType(_, _))));
+    }
+
+    #[test]
+    fn help_command() {
+        #[rustfmt::skip]
+        let repl = Repl::builder()
+            .add("add", command! {
+                "Add two numbers",
+                long: "First paragraph.\n\nSecond paragraph.",
+                examples: ["add 1 2"],
+                (
+                    /// First number
+                    a: i32,
+                    b: i32,
+                    /// Print more
+                    --verbose|-v,
+                ) => |_a, _b, _v| Ok(CommandStatus::Done)
+            })
+            .add("add", command!("Add floats", (a: f32, b: f32) => |_a, _b| Ok(CommandStatus::Done)))
+            .build()
+            .unwrap();
+        let expected = [
+            "add a:i32 b:i32 [options]",
+            "  Add two numbers",
+            "",
+            "  First paragraph.",
+            "",
+            "  Second paragraph.",
+            "",
+            "Arguments:",
+            "  a:i32  First number",
+            "  b:i32",
+            "",
+            "Options:",
+            "  -v, --verbose  Print more",
+            "",
+            "Examples:",
+            "  add 1 2",
+            "",
+            "add a:f32 b:f32",
+            "  Add floats",
+            "",
+            "Arguments:",
+            "  a:f32",
+            "  b:f32",
+        ];
+        assert_eq!(repl.command_help("add").unwrap(), expected.join("\n"));
+        assert_eq!(repl.command_help("quit").unwrap(), "quit\n  Quit repl");
+        assert!(repl.command_help("ad").is_none());
+    }
+
+    #[test]
+    fn history_file() {
+        let path = std::env::temp_dir().join(format!("easy-repl-history-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let build = || {
+            Repl::builder()
+                .history_file(&path)
+                .history_max_size(3_usize)
+                .history_ignore_dups(true)
+                .build()
+                .unwrap()
+        };
+        let mut repl = build();
+        assert!(repl.source.history().unwrap().is_empty());
+        for line in ["a", "b", "b", "c"] {
+            repl.source.add_history_entry(line);
+            repl.save_history().unwrap();
+        }
+        // another session appends to the same file
+        let mut other = build();
+        other.source.add_history_entry("d");
+        other.save_history().unwrap();
+
+        let mut repl = build();
+        let history: Vec<_> = repl.source.history().unwrap().iter().cloned().collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(history, vec!["b", "c", "d"]);
+    }
+
+    #[test]
+    fn history_saved_before_execution() {
+        struct Source(rustyline::history::History);
+        impl LineSource for Source {
+            fn readline(&mut self, _prompt: &str) -> rustyline::Result<String> {
+                Ok("check".into())
+            }
+            fn add_history_entry(&mut self, line: &str) {
+                self.0.add(line);
+            }
+            fn history(&mut self) -> Option<&mut rustyline::history::History> {
+                Some(&mut self.0)
+            }
+        }
+
+        let path = std::env::temp_dir().join(format!("easy-repl-saved-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let calls = Calls::default();
+        let (file, saved) = (path.clone(), &calls);
+        let mut repl = Repl::builder()
+            .history_file(&path)
+            .line_source(Source(rustyline::history::History::new()))
+            .add(
+                "check",
+                command!("", () => || {
+                    saved.push(std::fs::read_to_string(&file)?);
+                    Ok(CommandStatus::Done)
+                }),
+            )
+            .build()
+            .unwrap();
+        repl.next().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(calls.take()[0].ends_with("check\n"));
+    }
+
+    #[test]
+    fn line_source() {
+        let calls = Calls::default();
+        let mut repl = Repl::builder()
+            .add("foo", calls.command("foo"))
+            .line_source("foo 1\n\nfoo 2\nquit\nfoo 3\n".as_bytes())
+            .build()
+            .unwrap();
+        let statuses: Vec<_> = (0..4).map(|_| repl.next().unwrap()).collect();
+        assert_eq!(statuses[..3], [LoopStatus::Continue; 3]);
+        assert_eq!(statuses[3], LoopStatus::Break);
+        repl.run().unwrap();
+        assert_eq!(calls.take(), ["foo 1", "foo 2", "foo 3"]);
+
+        struct Source(std::rc::Rc<std::cell::RefCell<Option<CommandCompleter>>>);
+        impl LineSource for Source {
+            fn readline(&mut self, _prompt: &str) -> rustyline::Result<String> {
+                Err(ReadlineError::Eof)
+            }
+            fn set_completer(&mut self, completer: CommandCompleter) {
+                *self.0.borrow_mut() = Some(completer);
+            }
+        }
+        let completer = std::rc::Rc::new(std::cell::RefCell::new(None));
+        #[rustfmt::skip]
+        let mut repl = Repl::builder()
+            .add("foo", command!("", () => || Ok(CommandStatus::Done)))
+            .line_source(Source(completer.clone()))
+            .build()
+            .unwrap();
+        let complete = |line: &str| {
+            let completer = completer.borrow();
+            completer.as_ref().unwrap().complete(line, line.len())
+        };
+        assert_eq!(complete("f"), Some((0, vec!["foo".into()])));
+        repl.add_command("fizz", command!("", () => || Ok(CommandStatus::Done)))
+            .unwrap();
+        assert_eq!(complete("f"), Some((0, vec!["fizz".into(), "foo".into()])));
+        assert_eq!(repl.next().unwrap(), LoopStatus::Break);
+    }
+
+    #[test]
+    fn command_chaining() {
+        let calls = Calls::default();
+        let build = |chaining| {
+            Repl::builder()
+                .add("ok", calls.command("ok"))
+                .add("fail", fail())
+                .with_chaining(chaining)
+                .build()
+                .unwrap()
+        };
+        let mut repl = build(true);
+        let mut run = |line: &str| (repl.execute_line(line).unwrap(), calls.take());
+        assert_eq!(
+            run("ok 1; ok 2 && ok 3 || ok 4"),
+            (
+                Ok(LoopStatus::Continue),
+                vec!["ok 1".into(), "ok 2".into(), "ok 3".into()]
+            )
+        );
+        assert_eq!(
+            run("fail && ok 1 || ok 2 && ok 3"),
+            (Ok(LoopStatus::Continue), vec!["ok 2".into(), "ok 3".into()])
+        );
+        assert_eq!(
+            run("ok 1 && fail || fail"),
+            (Err("failed".into()), vec!["ok 1".into()])
+        );
+        assert_eq!(
+            run("ok ';' && ok 'a&&b'"),
+            (
+                Ok(LoopStatus::Continue),
+                vec!["ok ';'".into(), "ok 'a&&b'".into()]
+            )
+        );
+        assert_eq!(
+            run("ok 1; quit; ok 2"),
+            (Ok(LoopStatus::Break), vec!["ok 1".into()])
+        );
+        assert_eq!(
+            run("ok 1 &&"),
+            (Err("syntax error near '&&'".into()), vec![])
+        );
+        drop(repl);
+
+        let mut repl = build(false);
+        assert!(repl.execute_line("ok 1; ok 2").unwrap().is_ok());
+        assert_eq!(calls.take(), ["ok '1;' ok 2"]);
+    }
+
+    #[test]
+    fn variables() {
+        let calls = Calls::default();
+        let build = |env| {
+            let mut repl = Repl::builder()
+                .add("foo", calls.command("foo"))
+                .with_variables(true)
+                .with_env_variables(env)
+                .variable("dev", "dev 1")
+                .build()
+                .unwrap();
+            repl.env_var = |name| (name == "EASY_REPL_TEST_VAR").then(|| "from env".into());
+            repl
+        };
+        let mut repl = build(false);
+        let mut run = |line: &str| {
+            let result = repl.execute_line(line).unwrap().map(|_| ());
+            (result, calls.take())
+        };
+        assert_eq!(
+            run("foo $dev ${dev}x"),
+            (Ok(()), vec!["foo 'dev 1' 'dev 1x'".into()])
+        );
+        assert_eq!(run("set id 42"), (Ok(()), vec![]));
+        assert_eq!(
+            run(r#"foo '$id' \$id "$id""#),
+            (Ok(()), vec!["foo '$id' '$id' 42".into()])
+        );
+        assert_eq!(run("unset id dev"), (Ok(()), vec![]));
+        assert_eq!(
+            run("foo $id"),
+            (Err("undefined variable: id".into()), vec![])
+        );
+        assert_eq!(
+            run("set 1x 2").0.unwrap_err(),
+            "invalid variable name: '1x'"
+        );
+        assert_eq!(
+            run("foo $EASY_REPL_TEST_VAR").0.unwrap_err(),
+            "undefined variable: EASY_REPL_TEST_VAR"
+        );
+        drop(repl);
+
+        let mut repl = build(true);
+        assert!(repl
+            .execute_line("foo $EASY_REPL_TEST_VAR")
+            .unwrap()
+            .is_ok());
+        assert_eq!(calls.take(), ["foo 'from env'"]);
+        assert_eq!(repl.variable("dev").as_deref(), Some("dev 1"));
+        assert!(repl.help().contains("vars"));
+
+        let repl = Repl::builder()
+            .add("set", command!("", () => || Ok(CommandStatus::Done)))
+            .with_variables(true)
+            .build();
+        assert!(matches!(repl, Err(BuilderError::ReservedName(_))));
+    }
+
+    #[test]
+    fn suggestions() {
+        use crate::testing::ReplTester;
+        let calls = Calls::default();
+        let builder = || {
+            Repl::builder()
+                .add("status", command!("", () => || Ok(CommandStatus::Done)))
+                .add("stats", command!("", () => || Ok(CommandStatus::Done)))
+                .group("net", |g| {
+                    g.add("show", command!("", () => || Ok(CommandStatus::Done)))
+                })
+                .with_aliases(true)
+        };
+        let mut tester = ReplTester::new(builder()).unwrap();
+        let result = tester.assert_error("sattus", "command not found: sattus");
+        assert!(
+            result.output.contains("\nDid you mean: status?\n"),
+            "{}",
+            result.output
+        );
+        let result = tester.assert_error("stat", "command not found: stat");
+        assert!(!result.output.contains("Did you mean"));
+        let result = tester.line("stauts");
+        assert!(result.output.contains("Did you mean: stats or status?"));
+        let result = tester.line("net shwo");
+        assert!(result.output.contains("Did you mean: net show?"));
+        tester.assert_ok("alias verbose status");
+        let result = tester.line("help vrebose");
+        assert!(result.output.contains("Did you mean: verbose?"));
+        let result = tester.line("xyz");
+        assert!(!result.output.contains("Did you mean"));
+
+        let mut tester = ReplTester::new(builder().suggestion_distance(0usize)).unwrap();
+        assert!(!tester.line("sattus").output.contains("Did you mean"));
+
+        let mut repl = builder()
+            .add("echo", calls.command("echo"))
+            .with_autocorrect(true)
+            .line_source("n\ny\n".as_bytes())
+            .build()
+            .unwrap();
+        assert!(repl.execute_line("ehco a").unwrap().is_err());
+        assert!(repl.execute_line("ehco b").unwrap().is_ok());
+        // no more input, so not confirmed
+        assert!(repl.execute_line("ehco c").unwrap().is_err());
+        // multiple suggestions
+        assert!(repl.execute_line("stauts").unwrap().is_err());
+        assert_eq!(calls.take(), ["echo b"]);
+    }
+
+    #[test]
+    fn aliases() {
+        let calls = Calls::default();
+        let mut repl = Repl::builder()
+            .add("status", calls.command("status"))
+            .with_aliases(true)
+            .build()
+            .unwrap();
+        let mut run = |line: &str| {
+            let result = repl.execute_line(line).unwrap().map(|_| ());
+            (result, calls.take())
+        };
+        assert_eq!(run("alias st \"status --verbose\""), (Ok(()), vec![]));
+        assert_eq!(
+            run("st 'a b' c"),
+            (Ok(()), vec!["status --verbose 'a b' c".into()])
+        );
+        assert_eq!(run("alias s2 st x"), (Ok(()), vec![]));
+        assert_eq!(run("s2 y"), (Ok(()), vec!["status --verbose x y".into()]));
+        // prediction works with aliases
+        assert_eq!(run("s2"), (Ok(()), vec!["status --verbose x".into()]));
+        assert_eq!(
+            run("alias status x").0.unwrap_err(),
+            "alias name conflicts with a command: 'status'"
+        );
+        assert_eq!(run("alias x1 x2").0, Ok(()));
+        assert_eq!(run("alias x2 x1").0, Ok(()));
+        assert_eq!(run("x1").0.unwrap_err(), "alias recursion: x1 -> x2 -> x1");
+        assert_eq!(run("unalias x1 x").0.unwrap_err(), "alias not found: x");
+        assert!(run("x2").0.unwrap_err().contains("command not found"));
+
+        let help = repl.help();
+        assert!(
+            help.ends_with("Aliases:\n\n  s2  st x\n  st  status --verbose\n  x2  x1"),
+            "{}",
+            help
+        );
+        assert_eq!(
+            repl.command_help("st").unwrap(),
+            "st\n  Alias for: status --verbose"
+        );
+        assert!(matches!(
+            repl.add_command("st", command!("", () => || Ok(CommandStatus::Done))),
+            Err(BuilderError::DuplicateCommands(_))
+        ));
+    }
+
+    #[test]
+    fn run_script() {
+        let script = "# comment\n\nfoo 1\nfoo x\nbar\n  foo 2\nquit\nfoo 3\n";
+        #[rustfmt::skip]
+        let build = |stop| Repl::builder()
+            .add("foo", command!("", (_x: i32) => |_x| Ok(CommandStatus::Done)))
+            .stop_script_on_error(stop)
+            .build()
+            .unwrap();
+
+        let summary = build(true).run_script(script.as_bytes()).unwrap();
+        assert_eq!(summary.executed, 2);
+        assert!(!summary.quit);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(summary.failures[0].location, "<script>:4");
+        assert_eq!(summary.failures[0].line, "foo x");
+
+        let summary = build(false).run_script(script.as_bytes()).unwrap();
+        assert_eq!(summary.executed, 5);
+        assert!(summary.quit);
+        let locations: Vec<_> = summary
+            .failures
+            .iter()
+            .map(|f| f.location.as_str())
+            .collect();
+        assert_eq!(locations, ["<script>:4", "<script>:5"]);
+        assert_eq!(summary.failures[1].error, "command not found: bar");
+    }
+
+    #[test]
+    fn source_file() {
+        let dir = std::env::temp_dir().join(format!("easy-repl-source-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let inner = dir.join("inner.txt");
+        let outer = dir.join("outer.txt");
+        std::fs::write(&inner, format!("foo 2\nsource {}\n", outer.display())).unwrap();
+        std::fs::write(&outer, format!("foo 1\nsource {}\n", inner.display())).unwrap();
+
+        let calls = Calls::default();
+        let mut repl = Repl::builder()
+            .add("foo", calls.command("foo"))
+            .build()
+            .unwrap();
+        let summary = repl.run_file(&outer).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(calls.take(), ["foo 1", "foo 2"]);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(
+            summary.failures[0].location,
+            format!("{}:2", outer.display())
+        );
+        // the nested error is reported with inner file location
+        let error = &summary.failures[0].error;
+        assert!(
+            error.contains(&format!("{}:2", inner.display())),
+            "{}",
+            error
+        );
+        assert!(error.contains("recursive execution"), "{}", error);
+    }
+
+    #[test]
+    fn interrupted_command() {
+        #[rustfmt::skip]
+        let builder = Repl::builder()
+            .with_chaining(true)
+            .add("long", command!("", (ctx: &mut ReplContext) => |ctx: &mut ReplContext| {
+                // as if CTRL-C has been pressed
+                ctx.cancellation().cancel();
+                ctx.cancellation().check()?;
+                Ok(CommandStatus::Done)
+            }))
+            .add("ignore", command!("", (ctx: &mut ReplContext) => |ctx: &mut ReplContext| {
+                ctx.cancellation().cancel();
+                Ok(CommandStatus::Done)
+            }))
+            .add("echo", command!("", (ctx: &mut ReplContext, text: String) => |ctx: &mut ReplContext, text| {
+                writeln!(ctx.out(), "{}", text)?;
+                Ok(CommandStatus::Done)
+            }));
+        let mut tester = crate::testing::ReplTester::new(builder).unwrap();
+        let result = tester.assert_error("long && echo a", "interrupted");
+        assert_eq!(result.output, "Interrupted\n");
+        assert_eq!(tester.line("ignore || echo b").output, "Interrupted\nb\n");
+        tester.assert_output("echo c", "c\n");
+    }
+
+    #[test]
+    fn interrupt_policy() {
+        // `None` is CTRL-C
+        struct Source(std::vec::IntoIter<Option<&'static str>>);
+        impl LineSource for Source {
+            fn readline(&mut self, _prompt: &str) -> rustyline::Result<String> {
+                match self.0.next() {
+                    Some(Some(line)) => Ok(line.into()),
+                    Some(None) => Err(ReadlineError::Interrupted),
+                    None => Err(ReadlineError::Eof),
+                }
+            }
+        }
+        let statuses = |policy: InterruptPolicy| {
+            let mut repl = Repl::builder()
+                .add("foo", command!("", () => || Ok(CommandStatus::Done)))
+                .interrupt_policy(policy)
+                .out(Box::new(std::io::sink()) as Box<dyn Write>)
+                .line_source(Source(vec![None, Some("foo"), None, None].into_iter()))
+                .build()
+                .unwrap();
+            (0..4).map(|_| repl.next().unwrap()).collect::<Vec<_>>()
+        };
+        use LoopStatus::*;
+        assert_eq!(statuses(InterruptPolicy::Quit)[0], Break);
+        assert_eq!(
+            statuses(InterruptPolicy::Clear),
+            [Continue, Continue, Continue, Continue]
+        );
+        assert_eq!(
+            statuses(InterruptPolicy::DoublePress),
+            [Continue, Continue, Continue, Break]
+        );
+
+        // CTRL-C on a non-empty line of the default editor
+        let interrupted = |policy: InterruptPolicy| {
+            let mut repl = Repl::builder()
+                .interrupt_policy(policy)
+                .out(Box::new(std::io::sink()) as Box<dyn Write>)
+                .build()
+                .unwrap();
+            match &repl.source {
+                ReplSource::Rustyline(source) => source.interrupt.set_empty(false),
+                ReplSource::Custom(_) => unreachable!(),
+            }
+            repl.readline_error(ReadlineError::Interrupted).unwrap()
+        };
+        assert_eq!(interrupted(InterruptPolicy::Quit), Break);
+        assert_eq!(interrupted(InterruptPolicy::Clear), Continue);
+        assert_eq!(interrupted(InterruptPolicy::DoublePress), Continue);
+    }
+
+    #[test]
+    fn background_jobs() {
+        use crate::{job_command, jobs::JobContext};
+        #[rustfmt::skip]
+        let builder = Repl::builder()
+            .with_jobs(true)
+            .add("sum", job_command!("", (x: i32, y: i32) => |x, y| move |job: &mut JobContext| {
+                writeln!(job.out(), "{}", x + y)?;
+                Ok(CommandStatus::Done)
+            }))
+            .add("block", job_command!("", () => || |job: &mut JobContext| loop {
+                job.cancellation().check()?;
+                std::thread::yield_now();
+            }))
+            .add("echo", echo());
+        let mut tester = crate::testing::ReplTester::new(builder).unwrap();
+        tester.assert_output("sum 1 2", "3\n");
+        tester.assert_output("sum 1 2 &", "[1] sum 1 2\n");
+        tester.assert_output("wait 1", "3\n[1] Done  sum 1 2\n");
+        tester.assert_error("echo a &", "command cannot be executed in background: echo");
+        tester.assert_error("wait 1", "no such job: 1");
+
+        tester.assert_output("block&", "[2] block\n");
+        tester.assert_output("jobs", "[2] Running  block\n");
+        tester.assert_ok("kill %2");
+        let result = tester.assert_error("wait", "job failed: [2]");
+        assert_eq!(result.output, "[2] Killed  block\nError: job failed: [2]\n");
+        tester.assert_output("jobs", "");
+    }
+
+    #[test]
+    fn output_redirection() {
+        use crate::{job_command, jobs::JobContext};
+        let dir = std::env::temp_dir().join(format!("easy-repl-redirect-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+
+        #[rustfmt::skip]
+        let builder = Repl::builder()
+            .with_redirection(true)
+            .with_jobs(true)
+            .with_chaining(true)
+            .with_aliases(true)
+            .add("echo", echo())
+            .add("fail", fail())
+            .add("sum", job_command!("", (x: i32, y: i32) => |x, y| move |job: &mut JobContext| {
+                writeln!(job.out(), "{}", x + y)?;
+                Ok(CommandStatus::Done)
+            }));
+        let mut tester = crate::testing::ReplTester::new(builder).unwrap();
+        tester.assert_output(&format!("echo a > {}", path("out")), "");
+        tester.assert_output(&format!("echo 'b c'>>{}", path("out")), "");
+        assert_eq!(read("out"), "a\nb c\n");
+        tester.assert_output(&format!("help echo > '{}'", path("help")), "");
+        assert!(read("help").contains("echo text:String"));
+        tester.assert_output(&format!("sum 1 2 > {}", path("sum")), "");
+        assert_eq!(read("sum"), "3\n");
+        tester.assert_ok(&format!("sum 2 2 > {} &", path("job")));
+        tester.assert_output("wait", &format!("[1] Done  sum 2 2 > {}\n", path("job")));
+        assert_eq!(read("job"), "4\n");
+
+        // errors are not redirected
+        let result = tester.assert_error(&format!("echo > {}", path("err")), "wrong number");
+        assert!(result.output.contains("Usage:"));
+        assert_eq!(read("err"), "");
+        tester.assert_ok("alias both 'echo ok; fail'");
+        let result = tester.assert_error(&format!("both > {}", path("alias")), "failed");
+        assert_eq!(result.output, "Error: failed\n");
+        assert_eq!(read("alias"), "ok\n");
+        tester.assert_error("echo a >", "expected a single file name after '>'");
+        let missing = dir.join("missing").join("file");
+        tester.assert_error(&format!("echo a > {}", missing.display()), "could not open");
+        tester.assert_output("echo '>'", ">\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pipelines() {
+        use crate::{job_command, jobs::JobContext};
+
+        #[rustfmt::skip]
+        let builder = Repl::builder()
+            .with_pipelines(true)
+            .with_chaining(true)
+            .with_jobs(true)
+            .with_aliases(true)
+            .add("logs", command!("", (ctx: &mut ReplContext) => |ctx: &mut ReplContext| {
+                for i in 0..30 {
+                    let level = if i % 3 == 0 { "ERROR" } else { "INFO" };
+                    writeln!(ctx.out(), "{} {}", level, i)?;
+                }
+                Ok(CommandStatus::Done)
+            }))
+            .add("upper", command! {
+                "",
+                reads_input: true,
+                (ctx: &mut ReplContext) => |ctx: &mut ReplContext| {
+                    let mut text = String::new();
+                    ctx.input().unwrap().read_to_string(&mut text)?;
+                    write!(ctx.out(), "{}", text.to_uppercase())?;
+                    Ok(CommandStatus::Done)
+                }
+            })
+            .add("rev", job_command! {
+                "",
+                reads_input: true,
+                () => || |job: &mut JobContext| {
+                    let mut text = String::new();
+                    if let Some(input) = job.input() {
+                        input.read_to_string(&mut text)?;
+                    }
+                    writeln!(job.out(), "{}", text.trim().chars().rev().collect::<String>())?;
+                    Ok(CommandStatus::Done)
+                }
+            })
+            .add("fail", fail());
+        let mut tester = crate::testing::ReplTester::new(builder).unwrap();
+        tester.assert_output("logs | grep ERROR | head 2", "ERROR 0\nERROR 3\n");
+        tester.assert_output("logs|grep -v ERROR|tail 1", "INFO 29\n");
+        tester.assert_output("logs | grep ERROR | count", "10\n");
+        tester.assert_output("logs | head 1 | upper | rev", "0 RORRE\n");
+        tester.assert_output("help | grep -i quit", "  quit     Quit repl\n");
+        tester.assert_output("logs | count; logs | count", "30\n30\n");
+        tester.assert_output("fail || logs | count", "Error: failed\n30\n");
+
+        tester.assert_error("logs | logs", "command does not read input: logs");
+        tester.assert_error("fail | count", "failed");
+        tester.assert_ok("alias lf 'logs; fail'");
+        let result = tester.assert_error("lf | count", "failed");
+        assert_eq!(result.output, "Error: failed\n");
+        tester.assert_error("count", "no input, use after '|'");
+        tester.assert_error("logs |", "syntax error near '|'");
+        tester.assert_error("logs | rev &", "pipeline cannot be executed in background");
     }
 }
@@ -0,0 +1,154 @@
+//! Sources of input lines for the REPL.
+//!
+//! By default [`Repl`](crate::Repl) reads lines using [`rustyline`], which provides line
+//! editing, history and completion. A different [`LineSource`] can be configured with
+//! [`ReplBuilder::line_source`](crate::repl::ReplBuilder::line_source), e.g. to read commands
+//! from a pipe or to embed the REPL in an application that has its own input handling.
+//! Any [`BufRead`] can be used directly as a line source:
+//! ```rust
+//! # use easy_repl::{Repl, CommandStatus, command};
+//! let mut repl = Repl::builder()
+//!     .add("hello", command! {
+//!         "Say hello",
+//!         (name: String) => |name| {
+//!             println!("Hello {}!", name);
+//!             Ok(CommandStatus::Done)
+//!         }
+//!     })
+//!     .line_source("hello world\nhello there\n".as_bytes())
+//!     .build()
+//!     .expect("Failed to build REPL");
+//! repl.run().expect("Critical REPL error");
+//! ```
+
+use std::io::BufRead;
+
+use rustyline::{error::ReadlineError, history::History};
+
+pub use crate::completion::CommandCompleter;
+use crate::completion::Completion;
+use crate::interrupt::PromptInterrupt;
+
+/// Source of input lines for the REPL.
+///
+/// Only [`LineSource::readline`] is required, other methods have default implementations
+/// for sources that do not support history or completion.
+pub trait LineSource {
+    /// Read the next line, displaying the prompt if the source supports it.
+    ///
+    /// Returns [`ReadlineError::Eof`] at the end of input and [`ReadlineError::Interrupted`]
+    /// when the user interrupted reading (CTRL-C). The line should not contain the trailing
+    /// newline.
+    fn readline(&mut self, prompt: &str) -> rustyline::Result<String>;
+
+    /// Add a line to history. Called for each non-empty line before it is executed.
+    fn add_history_entry(&mut self, _line: &str) {}
+
+    /// History of this source, [`None`] if the source has no history.
+    ///
+    /// It is used to load and save the history file and is available to commands
+    /// through [`ReplContext::history`](crate::ReplContext::history).
+    fn history(&mut self) -> Option<&mut History> {
+        None
+    }
+
+    /// Completion hook, called with a new completer whenever the set of commands changes.
+    fn set_completer(&mut self, _completer: CommandCompleter) {}
+}
+
+/// Reads lines without displaying the prompt, e.g. from a file or a pipe.
+impl<R: BufRead> LineSource for R {
+    fn readline(&mut self, _prompt: &str) -> rustyline::Result<String> {
+        let mut line = String::new();
+        if BufRead::read_line(self, &mut line)? == 0 {
+            return Err(ReadlineError::Eof);
+        }
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+        Ok(line)
+    }
+}
+
+/// The default line source, [`rustyline::Editor`] with command completion.
+pub(crate) struct RustylineSource {
+    pub(crate) editor: rustyline::Editor<Completion>,
+    pub(crate) interrupt: PromptInterrupt,
+}
+
+impl LineSource for RustylineSource {
+    fn readline(&mut self, prompt: &str) -> rustyline::Result<String> {
+        self.editor.readline(prompt)
+    }
+
+    fn add_history_entry(&mut self, line: &str) {
+        self.editor.add_history_entry(line);
+    }
+
+    fn history(&mut self) -> Option<&mut History> {
+        Some(self.editor.history_mut())
+    }
+
+    fn set_completer(&mut self, completer: CommandCompleter) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.completer = completer;
+        }
+    }
+}
+
+/// Line source of a [`Repl`](crate::Repl), the concrete type of the default source is kept
+/// so that it can be moved to another thread, see [`AsyncRepl`](crate::AsyncRepl).
+pub(crate) enum ReplSource<'a> {
+    Rustyline(Box<RustylineSource>),
+    Custom(Box<dyn LineSource + 'a>),
+}
+
+impl<'a> ReplSource<'a> {
+    fn source(&mut self) -> &mut dyn LineSource {
+        match self {
+            ReplSource::Rustyline(source) => source.as_mut(),
+            ReplSource::Custom(source) => source.as_mut(),
+        }
+    }
+
+    /// Whether the line was empty when reading was interrupted, unknown for custom sources.
+    pub(crate) fn interrupted_empty(&self) -> bool {
+        match self {
+            ReplSource::Rustyline(source) => source.interrupt.was_empty(),
+            ReplSource::Custom(_) => true,
+        }
+    }
+}
+
+impl<'a> LineSource for ReplSource<'a> {
+    fn readline(&mut self, prompt: &str) -> rustyline::Result<String> {
+        self.source().readline(prompt)
+    }
+
+    fn add_history_entry(&mut self, line: &str) {
+        self.source().add_history_entry(line)
+    }
+
+    fn history(&mut self) -> Option<&mut History> {
+        self.source().history()
+    }
+
+    fn set_completer(&mut self, completer: CommandCompleter) {
+        self.source().set_completer(completer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buf_read_source() {
+        let mut source = "first\r\nsecond\n\nlast".as_bytes();
+        assert_eq!(source.readline("> ").unwrap(), "first");
+        assert_eq!(source.readline("> ").unwrap(), "second");
+        assert_eq!(source.readline("> ").unwrap(), "");
+        assert_eq!(source.readline("> ").unwrap(), "last");
+        assert!(matches!(source.readline("> "), Err(ReadlineError::Eof)));
+        assert!(source.history().is_none());
+    }
+}
@@ -0,0 +1,274 @@
+//! Utilities for testing REPLs.
+//!
+//! [`ReplTester`] executes input lines one by one, just like [`Repl::run`] would, capturing
+//! everything written to the REPL output and recording the result of each line:
+//! ```rust
+//! # use easy_repl::{Repl, CommandStatus, ReplContext, command, testing::ReplTester};
+//! let mut tester = ReplTester::new(Repl::builder()
+//!     .add("add", command! {
+//!         "Add X to Y",
+//!         (ctx: &mut ReplContext, x: i32, y: i32) => |ctx: &mut ReplContext, x, y| {
+//!             writeln!(ctx.out(), "{}", x + y)?;
+//!             Ok(CommandStatus::Done)
+//!         }
+//!     }))
+//!     .expect("Failed to build REPL");
+//!
+//! tester.assert_output("add 1 2", "3\n");
+//! tester.assert_error("add 1 x", "failed to parse argument value 'x'");
+//! tester.assert_error("foo", "command not found: foo");
+//! tester.assert_quits("quit");
+//! ```
+//!
+//! Note that only the output written to [`ReplBuilder::out`](crate::repl::ReplBuilder::out) or
+//! [`ReplContext::out`](crate::ReplContext::out) is captured, output printed directly to
+//! stdout/stderr is not.
+
+use std::io::Write;
+
+use crate::command::CriticalError;
+use crate::repl::{BuilderError, LoopStatus, Repl, ReplBuilder, SharedBuffer};
+
+/// Result of executing a single line by [`ReplTester`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineResult {
+    /// The line that has been executed
+    pub line: String,
+    /// Everything written to the REPL output while executing the line
+    pub output: String,
+    /// State of the REPL after executing the line, [`LoopStatus::Break`] on critical errors
+    pub status: LoopStatus,
+    /// Error message if the line failed
+    pub error: Option<String>,
+    /// Whether the error is a [`CriticalError`] that would stop [`Repl::run`]
+    pub critical: bool,
+}
+
+impl LineResult {
+    /// Returns `true` if the line has been executed without errors.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Returns `true` if the REPL would quit after this line.
+    pub fn quits(&self) -> bool {
+        self.status == LoopStatus::Break
+    }
+}
+
+/// Drives a [`Repl`] with scripted input, see the [module level documentation](self).
+pub struct ReplTester<'a> {
+    repl: Repl<'a>,
+    output: SharedBuffer,
+    results: Vec<LineResult>,
+}
+
+impl<'a> ReplTester<'a> {
+    /// Build the REPL from given builder, replacing its output and input with buffers.
+    pub fn new(builder: ReplBuilder<'a>) -> Result<Self, BuilderError> {
+        let output = SharedBuffer::default();
+        let repl = builder
+            .out(Box::new(output.clone()) as Box<dyn Write>)
+            .line_source(std::io::empty())
+            .build()?;
+        Ok(ReplTester {
+            repl,
+            output,
+            results: vec![],
+        })
+    }
+
+    /// Access the REPL, e.g. to check its state.
+    pub fn repl(&mut self) -> &mut Repl<'a> {
+        &mut self.repl
+    }
+
+    /// Results of all the lines executed so far.
+    pub fn results(&self) -> &[LineResult] {
+        &self.results
+    }
+
+    /// Output of all the lines executed so far.
+    pub fn output(&self) -> String {
+        self.results.iter().map(|r| r.output.as_str()).collect()
+    }
+
+    /// Execute a single line and record its result.
+    pub fn line(&mut self, line: &str) -> &LineResult {
+        let (status, error, critical) = match self.repl.execute_line(line) {
+            Ok(Ok(status)) => (status, None, false),
+            Ok(Err(err)) => (LoopStatus::Continue, Some(err), false),
+            Err(err) => {
+                // critical errors are not printed by the REPL, but returned from `Repl::run`
+                let critical = err.downcast_ref::<CriticalError>().is_some();
+                (LoopStatus::Break, Some(err.to_string()), critical)
+            }
+        };
+        self.results.push(LineResult {
+            line: line.into(),
+            output: self.output.take(),
+            status,
+            error,
+            critical,
+        });
+        self.results.last().unwrap()
+    }
+
+    /// Execute lines until the REPL would quit, returning results of the executed lines.
+    pub fn lines<I, S>(&mut self, lines: I) -> &[LineResult]
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let start = self.results.len();
+        for line in lines {
+            if self.line(line.as_ref()).quits() {
+                break;
+            }
+        }
+        &self.results[start..]
+    }
+
+    /// Execute a line and assert that it succeeded.
+    #[track_caller]
+    pub fn assert_ok(&mut self, line: &str) -> &LineResult {
+        let result = self.line(line);
+        assert!(
+            result.is_ok(),
+            "line '{}' failed: {}",
+            line,
+            result.error.as_ref().unwrap()
+        );
+        result
+    }
+
+    /// Execute a line and assert that it failed with an error containing `message`.
+    #[track_caller]
+    pub fn assert_error(&mut self, line: &str, message: &str) -> &LineResult {
+        let result = self.line(line);
+        match result.error.as_ref() {
+            Some(err) => assert!(
+                err.contains(message),
+                "line '{line}' failed with '{err}', expected error containing '{message}'"
+            ),
+            None => panic!("line '{line}' succeeded, expected error containing '{message}'"),
+        }
+        result
+    }
+
+    /// Execute a line and assert that it succeeded with exactly the given output.
+    #[track_caller]
+    pub fn assert_output(&mut self, line: &str, output: &str) -> &LineResult {
+        let result = self.assert_ok(line);
+        assert_eq!(result.output, output, "unexpected output of line '{line}'");
+        result
+    }
+
+    /// Execute a line and assert that the REPL would quit after it.
+    #[track_caller]
+    pub fn assert_quits(&mut self, line: &str) -> &LineResult {
+        let result = self.line(line);
+        assert!(result.quits(), "line '{line}' did not quit the REPL");
+        result
+    }
+}
+
+/// Commands shared by the tests of this crate.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use std::cell::RefCell;
+
+    use crate::{command, Command, CommandStatus, ReplContext};
+
+    /// Log of executed commands.
+    #[derive(Default)]
+    pub(crate) struct Calls(RefCell<Vec<String>>);
+
+    impl Calls {
+        /// Command accepting any arguments, which records `name` followed by the arguments,
+        /// quoted like in the input line, e.g. `"ok 'a b' c"`.
+        pub(crate) fn command<'a>(&'a self, name: &str) -> Command<'a> {
+            let name = name.to_string();
+            Command::new("", move |args| {
+                let quoted = args
+                    .iter()
+                    .map(|arg| format!(" {}", shell_words::quote(arg)));
+                self.push(std::iter::once(name.clone()).chain(quoted).collect());
+                Ok(CommandStatus::Done)
+            })
+        }
+
+        pub(crate) fn push(&self, call: String) {
+            self.0.borrow_mut().push(call);
+        }
+
+        /// Calls recorded since the last call to `take`.
+        pub(crate) fn take(&self) -> Vec<String> {
+            std::mem::take(&mut *self.0.borrow_mut())
+        }
+    }
+
+    /// Command that writes its single argument to the output.
+    pub(crate) fn echo<'a>() -> Command<'a> {
+        command!("", (ctx: &mut ReplContext, text: String) => |ctx: &mut ReplContext, text| {
+            writeln!(ctx.out(), "{}", text)?;
+            Ok(CommandStatus::Done)
+        })
+    }
+
+    /// Command that always fails with "failed".
+    pub(crate) fn fail<'a>() -> Command<'a> {
+        command!("", () => || Err(anyhow::anyhow!("failed")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::echo;
+    use super::*;
+    use crate::{command, CommandStatus, Critical};
+
+    fn tester() -> ReplTester<'static> {
+        #[rustfmt::skip]
+        let builder = Repl::builder()
+            .add("echo", echo())
+            .add("stop", command!("", () => || Ok(CommandStatus::Quit)))
+            .add("crash", command!("", () => || {
+                Err::<(), _>(std::io::Error::other("boom")).into_critical()?;
+                Ok(CommandStatus::Done)
+            }));
+        ReplTester::new(builder).unwrap()
+    }
+
+    #[test]
+    fn tester_lines() {
+        let mut tester = tester();
+        let results = tester.lines(["echo a", "", "echo", "ech b", "stop", "echo c"]);
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].output, "a\n");
+        assert!(results[1].is_ok() && results[1].output.is_empty());
+        assert!(results[2].output.contains("Usage:\n  echo text:String"));
+        assert!(results[2].error.is_some());
+        assert_eq!(results[3].output, "b\n");
+        assert!(results[4].quits());
+        assert_eq!(tester.results().len(), 5);
+        assert!(tester.output().starts_with("a\nError:"));
+    }
+
+    #[test]
+    fn tester_assertions() {
+        let mut tester = tester();
+        tester.assert_output("echo 'a b'", "a b\n");
+        tester.assert_error("foo", "command not found: foo");
+        tester.assert_quits("quit");
+        let result = tester.assert_quits("crash");
+        assert!(result.critical);
+        assert_eq!(result.error.as_deref(), Some("boom"));
+    }
+
+    #[test]
+    #[should_panic(expected = "line 'echo a' did not quit the REPL")]
+    fn tester_assertion_fails() {
+        tester().assert_quits("echo a");
+    }
+}